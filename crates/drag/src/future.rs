@@ -0,0 +1,214 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{CursorPosition, DragAction, DragImage, DragItem, DragResult, Error, Options};
+
+/// Outcome of a drag operation started with [`start_drag_async`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct DragOutcome {
+    pub result: DragResult,
+    pub cursor_position: CursorPosition,
+    /// The action performed by the drop target, if the item was dropped.
+    pub action: Option<DragAction>,
+}
+
+#[derive(Default)]
+struct Shared {
+    completed: bool,
+    outcome: Option<crate::Result<DragOutcome>>,
+    waker: Option<Waker>,
+}
+
+/// Future returned by [`start_drag_async`], resolving once the drag operation ends.
+pub struct DragFuture {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Future for DragFuture {
+    type Output = crate::Result<DragOutcome>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.outcome.take() {
+            Some(outcome) => Poll::Ready(outcome),
+            None => {
+                shared.waker.replace(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Resolves the [`DragFuture`] with the first outcome it is given.
+#[derive(Clone)]
+struct Completer {
+    sender: Arc<Sender>,
+}
+
+struct Sender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Completer {
+    fn complete(&self, outcome: crate::Result<DragOutcome>) {
+        self.sender.complete(outcome);
+    }
+}
+
+impl Sender {
+    fn complete(&self, outcome: crate::Result<DragOutcome>) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.completed {
+            return;
+        }
+        shared.completed = true;
+        shared.outcome.replace(outcome);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Sender {
+    // the backend dropped the drop callback without calling it, e.g. because the window was destroyed
+    fn drop(&mut self) {
+        self.complete(Err(Error::DragInterrupted));
+    }
+}
+
+fn channel() -> (DragFuture, Completer) {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    (
+        DragFuture {
+            shared: shared.clone(),
+        },
+        Completer {
+            sender: Arc::new(Sender { shared }),
+        },
+    )
+}
+
+/// Starts a drag operation and resolves to its outcome once it ends.
+///
+/// This function can be called from any thread, the drag operation is started on the GTK main thread,
+/// where `window` is called to get the window to drag from and `item` is called to create the dragged item,
+/// so its providers don't need to be [`Send`].
+///
+/// The drag operation can not be cancelled, see [`crate::start_drag`] for a [`crate::DragSession`] handle.
+#[cfg(target_os = "linux")]
+pub fn start_drag_async<G, E, I>(
+    window: G,
+    item: I,
+    image: impl Into<DragImage>,
+    options: Options,
+) -> DragFuture
+where
+    G: FnOnce() -> std::result::Result<gtk::ApplicationWindow, E> + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+    I: FnOnce() -> DragItem + Send + 'static,
+{
+    let (future, completer) = channel();
+    let image = image.into();
+    gtk::glib::MainContext::default().invoke(move || {
+        let result = window()
+            .map_err(|e| Error::WindowUnavailable(Box::new(e)))
+            .and_then(|window| start(&window, item(), image, options, completer.clone()));
+        if let Err(e) = result {
+            completer.complete(Err(e));
+        }
+    });
+    future
+}
+
+/// Starts a drag operation and resolves to its outcome once it ends.
+///
+/// This function can be called from any thread, the drag operation is started on the thread that owns the window,
+/// where `item` is called to create the dragged item, so its providers don't need to be [`Send`].
+///
+/// The drag operation can not be cancelled, see [`crate::start_drag`] for a [`crate::DragSession`] handle.
+#[cfg(not(target_os = "linux"))]
+pub fn start_drag_async<W, G, E, I>(
+    window: G,
+    item: I,
+    image: impl Into<DragImage>,
+    options: Options,
+) -> DragFuture
+where
+    W: raw_window_handle::HasRawWindowHandle,
+    G: FnOnce() -> std::result::Result<W, E> + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+    I: FnOnce() -> DragItem + Send + 'static,
+{
+    let (future, completer) = channel();
+    let image = image.into();
+    match window() {
+        Ok(window) => {
+            let raw_handle = window.raw_window_handle();
+            let handle = SendRawWindowHandle(raw_handle);
+            let completer_ = completer.clone();
+            let result = crate::platform_impl::run_on_main_thread(
+                &raw_handle,
+                Box::new(move || {
+                    if let Err(e) = start(&handle, item(), image, options, completer_.clone()) {
+                        completer_.complete(Err(e));
+                    }
+                }),
+            );
+            if let Err(e) = result {
+                completer.complete(Err(e));
+            }
+        }
+        Err(e) => completer.complete(Err(Error::WindowUnavailable(Box::new(e)))),
+    }
+    future
+}
+
+/// Moves the window handle to the thread that owns the window.
+#[cfg(not(target_os = "linux"))]
+struct SendRawWindowHandle(raw_window_handle::RawWindowHandle);
+
+#[cfg(not(target_os = "linux"))]
+unsafe impl Send for SendRawWindowHandle {}
+
+#[cfg(not(target_os = "linux"))]
+unsafe impl raw_window_handle::HasRawWindowHandle for SendRawWindowHandle {
+    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
+        self.0
+    }
+}
+
+fn start<#[cfg(not(target_os = "linux"))] W: raw_window_handle::HasRawWindowHandle>(
+    #[cfg(target_os = "linux")] window: &gtk::ApplicationWindow,
+    #[cfg(not(target_os = "linux"))] window: &W,
+    item: DragItem,
+    image: DragImage,
+    options: Options,
+    completer: Completer,
+) -> crate::Result<()> {
+    crate::start_drag(
+        window,
+        item,
+        image,
+        move |result, cursor_position| {
+            completer.complete(Ok(DragOutcome {
+                result,
+                cursor_position,
+                action: match result {
+                    DragResult::Dropped(action) => Some(action),
+                    DragResult::Cancel(_) => None,
+                },
+            }));
+        },
+        options,
+    )
+    .map(drop)
+}
@@ -0,0 +1,184 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+/// Derives the plain text representation of an HTML fragment.
+///
+/// Tags are stripped, the content of `script` and `style` elements is skipped,
+/// whitespace is collapsed, block elements and `<br>` are turned into line breaks and common entities are decoded.
+pub(crate) fn to_plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let tag;
+                (tag, rest) = match rest[1..].find('>') {
+                    Some(i) => (&rest[1..=i], &rest[i + 2..]),
+                    None => (&rest[1..], ""),
+                };
+
+                let closing = tag.starts_with('/');
+                let name = tag
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+
+                if !closing && (name == "script" || name == "style") {
+                    let closing_tag = format!("</{name}");
+                    rest = match rest.to_ascii_lowercase().find(&closing_tag) {
+                        Some(i) => &rest[i..],
+                        None => "",
+                    };
+                } else if name == "br" || is_block_element(&name) {
+                    push_line_break(&mut text);
+                } else if !closing && (name == "td" || name == "th") {
+                    // separate table cells with tabs
+                    while text.ends_with(' ') {
+                        text.pop();
+                    }
+                    if !(text.is_empty() || text.ends_with('\n')) {
+                        text.push('\t');
+                    }
+                }
+            }
+            '&' => {
+                let entity = rest[1..]
+                    .find(';')
+                    .filter(|i| *i <= 8)
+                    .and_then(|i| decode_entity(&rest[1..=i]).map(|c| (c, i + 2)));
+                match entity {
+                    Some((c, len)) => {
+                        text.push(c);
+                        rest = &rest[len..];
+                    }
+                    None => {
+                        text.push('&');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                if !(text.is_empty() || text.ends_with([' ', '\t', '\n'])) {
+                    text.push(' ');
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+            c => {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    text.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Whether the element starts on a new line when rendered.
+fn is_block_element(name: &str) -> bool {
+    matches!(
+        name,
+        "address"
+            | "article"
+            | "blockquote"
+            | "div"
+            | "dl"
+            | "dt"
+            | "dd"
+            | "footer"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "header"
+            | "hr"
+            | "li"
+            | "ol"
+            | "p"
+            | "pre"
+            | "section"
+            | "table"
+            | "tr"
+            | "ul"
+    )
+}
+
+fn push_line_break(text: &mut String) {
+    while text.ends_with(' ') {
+        text.pop();
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_plain_text;
+
+    #[test]
+    fn plain_text() {
+        for (html, text) in [
+            ("<p>Hello <b>world</b></p>", "Hello world"),
+            ("  spaced \n\t  out  ", "spaced out"),
+            ("one<br>two<br/>three<BR />four", "one\ntwo\nthree\nfour"),
+            (
+                "<h1>Title</h1><p>First</p><p>Second</p>",
+                "Title\nFirst\nSecond",
+            ),
+            ("<ul><li>one</li> <li>two</li></ul>", "one\ntwo"),
+            (
+                "<table><tr><td>a</td> <td>b</td></tr><tr><th>c</th></tr></table>",
+                "a\tb\nc",
+            ),
+            ("<script>alert('<p>x</p>')</script>after", "after"),
+            (
+                "<STYLE type=\"text/css\">p { color: red }</Style>after",
+                "after",
+            ),
+            ("<script>never closed", ""),
+            (
+                "a &amp; b &lt;c&gt; &quot;d&quot; &apos;e&apos;",
+                "a & b <c> \"d\" 'e'",
+            ),
+            ("&#233;&#xE9;&#XE9;", "ééé"),
+            ("a&nbsp;b", "a\u{a0}b"),
+            ("fish &chips; salt & vinegar", "fish &chips; salt & vinegar"),
+            ("&#xD800; &#99999999;", "&#xD800; &#99999999;"),
+            ("&verylongentityname;", "&verylongentityname;"),
+            ("unterminated <b", "unterminated"),
+            ("résumé <i>naïve</i>", "résumé naïve"),
+        ] {
+            assert_eq!(to_plain_text(html), text, "{html:?}");
+        }
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::path::Path;
+
+use crate::{DragImage, DragItem, Error, Image, PreparedImage, Result};
+
+/// Logical size of the previews of [`Image::Auto`] when [`DragImage::max_size`] is not set.
+const AUTO_PREVIEW_SIZE: u32 = 96;
+/// Number of files [`Image::Auto`] generates previews for.
+const AUTO_PREVIEWS: usize = 3;
+
+/// Whether the buffer holds `height` rows of `stride` bytes, each starting with `width` RGBA pixels.
+///
+/// The last row doesn't need to be padded to the stride.
+fn is_valid_rgba(width: u32, height: u32, stride: u32, pixels: &[u8]) -> bool {
+    let row_len = width as usize * 4;
+    width > 0
+        && height > 0
+        && stride as usize >= row_len
+        && (height as usize - 1)
+            .checked_mul(stride as usize)
+            .and_then(|len| len.checked_add(row_len))
+            .is_some_and(|len| len <= pixels.len())
+}
+
+/// Converts the RGBA pixels to tightly packed rows of premultiplied BGRA pixels.
+#[cfg(windows)]
+pub(crate) fn rgba_to_premultiplied_bgra(
+    width: u32,
+    height: u32,
+    stride: u32,
+    pixels: &[u8],
+) -> Vec<u8> {
+    let premultiply = |channel: u8, alpha: u8| ((channel as u16 * alpha as u16 + 127) / 255) as u8;
+
+    let mut bgra = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in pixels.chunks(stride as usize).take(height as usize) {
+        for pixel in row[..width as usize * 4].chunks_exact(4) {
+            let [r, g, b, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            bgra.extend_from_slice(&[premultiply(b, a), premultiply(g, a), premultiply(r, a), a]);
+        }
+    }
+    bgra
+}
+
+/// Converts the premultiplied RGBA pixels to straight alpha.
+#[cfg(target_os = "macos")]
+pub(crate) fn demultiply_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u16;
+        if alpha > 0 && alpha < 255 {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u16 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+}
+
+/// The logical size of a drag image of `width`×`height` pixels.
+///
+/// The size is scaled down to fit `max_size`, keeping the aspect ratio.
+pub(crate) fn logical_size(
+    width: u32,
+    height: u32,
+    scale_factor: f64,
+    max_size: Option<(u32, u32)>,
+) -> (f64, f64) {
+    let scale_factor = if scale_factor > 0. { scale_factor } else { 1. };
+    let (width, height) = (width as f64 / scale_factor, height as f64 / scale_factor);
+    let fit = max_size.map_or(1., |(max_width, max_height)| {
+        (max_width as f64 / width)
+            .min(max_height as f64 / height)
+            .min(1.)
+    });
+    (width * fit, height * fit)
+}
+
+/// Generates the previews of [`Image::Auto`] and decodes the images.
+pub(crate) fn resolve(image: DragImage, item: &DragItem, strict: bool) -> Result<DragImage> {
+    decoded(auto_previews(image, item, strict)?, strict)
+}
+
+/// Replaces [`Image::Auto`] with previews of the dragged files.
+///
+/// Only the first files get their own preview, the others share the last one so the stack still counts them.
+fn auto_previews(image: DragImage, item: &DragItem, strict: bool) -> Result<DragImage> {
+    if !matches!(image.image, Image::Auto) {
+        return Ok(image);
+    }
+
+    let files = match item {
+        DragItem::Files(files) => files.as_slice(),
+        DragItem::Composite(composite) => composite.files.as_slice(),
+        _ => &[],
+    };
+    let size = image
+        .max_size
+        .map_or(AUTO_PREVIEW_SIZE, |(width, height)| width.min(height));
+    let size = (size as f64 * image.scale_factor).round().max(1.) as u32;
+    let mut previews = load_previews(files.iter().take(AUTO_PREVIEWS), strict, |file| {
+        crate::platform_impl::auto_preview(&file.path, size).map(Image::Prepared)
+    })?;
+    if let Some(last) = previews.last().cloned() {
+        previews.resize(files.len(), last);
+    }
+
+    Ok(DragImage {
+        image: Image::Stack(previews),
+        ..image
+    })
+}
+
+/// Decodes the file and raw images to pixels when the `image-decode` feature is enabled,
+/// so every backend shows the same formats.
+#[cfg_attr(not(feature = "image-decode"), allow(unused_variables))]
+fn decoded(image: DragImage, strict: bool) -> Result<DragImage> {
+    #[cfg(feature = "image-decode")]
+    {
+        let target_size = image.max_size.map(|(width, height)| {
+            (
+                (width as f64 * image.scale_factor).round() as u32,
+                (height as f64 * image.scale_factor).round() as u32,
+            )
+        });
+        let scale_factor = image.scale_factor;
+        let previews = load_previews(image.image.into_previews(), strict, |preview| {
+            decode::decode(preview, scale_factor, target_size)
+        })?;
+        Ok(DragImage {
+            image: Image::Stack(previews),
+            ..image
+        })
+    }
+    #[cfg(not(feature = "image-decode"))]
+    Ok(image)
+}
+
+/// Loads the image the way the drag operation does.
+pub(crate) fn validate(image: &Image) -> Result<()> {
+    match image {
+        Image::Rgba {
+            width,
+            height,
+            stride,
+            pixels,
+        } => check_rgba(*width, *height, *stride, pixels),
+        Image::Stack(images) => images.iter().try_for_each(validate),
+        // the previews of `Image::Auto` are generated when dragging the files
+        Image::Prepared(_) | Image::Auto => Ok(()),
+        #[cfg(feature = "image-decode")]
+        Image::File(path) => decode::decode_bytes(&read_file(path)?, 1., None)
+            .map(drop)
+            .map_err(|message| decode_error(Some(path), message)),
+        #[cfg(feature = "image-decode")]
+        Image::Raw(bytes) => decode::decode_bytes(bytes, 1., None)
+            .map(drop)
+            .map_err(|message| decode_error(None, message)),
+        #[cfg(not(feature = "image-decode"))]
+        image => crate::platform_impl::validate_image(image),
+    }
+}
+
+/// Decodes the images to pixels.
+pub(crate) fn prepare(image: Image) -> Result<Image> {
+    match image {
+        Image::Stack(images) => images
+            .into_iter()
+            .map(prepare)
+            .collect::<Result<_>>()
+            .map(Image::Stack),
+        Image::Prepared(_) | Image::Auto => Ok(image),
+        Image::Rgba {
+            width,
+            height,
+            stride,
+            pixels,
+        } => {
+            check_rgba(width, height, stride, &pixels)?;
+            Ok(Image::Prepared(PreparedImage::from_rgba(
+                width, height, stride, &pixels,
+            )))
+        }
+        #[cfg(feature = "image-decode")]
+        image => prepare(decode::decode(image, 1., None)?),
+        #[cfg(not(feature = "image-decode"))]
+        image => crate::platform_impl::prepare_image(&image).map(Image::Prepared),
+    }
+}
+
+/// Loads each preview, leaving out the ones that fail to load unless `strict` is set.
+pub(crate) fn load_previews<P, T>(
+    previews: impl IntoIterator<Item = P>,
+    strict: bool,
+    mut load: impl FnMut(P) -> Result<T>,
+) -> Result<Vec<T>> {
+    let mut loaded = Vec::new();
+    for preview in previews {
+        match load(preview) {
+            Ok(preview) => loaded.push(preview),
+            Err(e) if strict => return Err(e),
+            Err(_) => {}
+        }
+    }
+    Ok(loaded)
+}
+
+/// Reads the file of an [`Image::File`], reporting a missing file as [`Error::ImageNotFound`].
+#[cfg(any(target_os = "linux", feature = "image-decode"))]
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::ImageNotFound,
+        _ => decode_error(Some(path), e),
+    })
+}
+
+/// Checks that the buffer of an [`Image::Rgba`] holds all of its pixels.
+pub(crate) fn check_rgba(width: u32, height: u32, stride: u32, pixels: &[u8]) -> Result<()> {
+    if is_valid_rgba(width, height, stride, pixels) {
+        Ok(())
+    } else {
+        Err(decode_error(
+            None,
+            format!(
+                "{} bytes don't hold {width}x{height} RGBA pixels with a stride of {stride} bytes",
+                pixels.len()
+            ),
+        ))
+    }
+}
+
+/// The error reported when the image fails to decode, with the file of an [`Image::File`].
+pub(crate) fn decode_error(path: Option<&Path>, message: impl ToString) -> Error {
+    Error::ImageDecode {
+        path: path.map(Path::to_path_buf),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(feature = "image-decode")]
+mod decode {
+    use super::{decode_error, read_file};
+    use crate::{Image, Result};
+    use resvg::{tiny_skia, usvg};
+
+    pub(super) fn decode(
+        image: Image,
+        scale_factor: f64,
+        target_size: Option<(u32, u32)>,
+    ) -> Result<Image> {
+        match image {
+            Image::File(path) => decode_bytes(&read_file(&path)?, scale_factor, target_size)
+                .map_err(|message| decode_error(Some(&path), message)),
+            Image::Raw(bytes) => decode_bytes(&bytes, scale_factor, target_size)
+                .map_err(|message| decode_error(None, message)),
+            // stacks are flattened by `into_previews`
+            image => Ok(image),
+        }
+    }
+
+    /// Decodes the raster image, or rasterizes the SVG image at its size scaled by `scale_factor`, fitting `target_size`.
+    pub(super) fn decode_bytes(
+        bytes: &[u8],
+        scale_factor: f64,
+        target_size: Option<(u32, u32)>,
+    ) -> std::result::Result<Image, String> {
+        if image::guess_format(bytes).is_ok() {
+            let pixels = image::load_from_memory(bytes)
+                .map_err(|e| e.to_string())?
+                .into_rgba8();
+            return Ok(Image::Rgba {
+                width: pixels.width(),
+                height: pixels.height(),
+                stride: pixels.width() * 4,
+                pixels: pixels.into_raw(),
+            });
+        }
+
+        let tree =
+            usvg::Tree::from_data(bytes, &usvg::Options::default()).map_err(|e| e.to_string())?;
+        let size = tree.size();
+        let (width, height) = (
+            size.width() as f64 * scale_factor,
+            size.height() as f64 * scale_factor,
+        );
+        let fit = target_size.map_or(1., |(max_width, max_height)| {
+            (max_width as f64 / width)
+                .min(max_height as f64 / height)
+                .min(1.)
+        });
+        let (width, height) = (
+            ((width * fit).round() as u32).max(1),
+            ((height * fit).round() as u32).max(1),
+        );
+
+        let mut pixmap = tiny_skia::Pixmap::new(width, height)
+            .ok_or_else(|| format!("invalid SVG size {width}x{height}"))?;
+        resvg::render(
+            &tree,
+            tiny_skia::Transform::from_scale(
+                width as f32 / size.width(),
+                height as f32 / size.height(),
+            ),
+            &mut pixmap.as_mut(),
+        );
+
+        // tiny-skia premultiplies the alpha
+        let pixels = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.demultiply();
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            })
+            .collect();
+        Ok(Image::Rgba {
+            width,
+            height,
+            stride: width * 4,
+            pixels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_rgba, logical_size};
+
+    #[test]
+    fn valid_rgba() {
+        for (width, height, stride, len, valid) in [
+            (1, 1, 4, 4, true),
+            (2, 2, 8, 16, true),
+            (2, 2, 8, 15, false),
+            // the last row doesn't need to be padded
+            (2, 2, 12, 20, true),
+            (2, 2, 12, 19, false),
+            (2, 2, 7, 16, false),
+            (0, 2, 8, 16, false),
+            (2, 0, 8, 16, false),
+            (u32::MAX, 1, u32::MAX, 16, false),
+            (1, u32::MAX, u32::MAX, 16, false),
+        ] {
+            assert_eq!(
+                is_valid_rgba(width, height, stride, &vec![0; len]),
+                valid,
+                "{width}x{height}, stride {stride}, {len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn scaled_logical_size() {
+        for ((width, height, scale_factor, max_size), size) in [
+            ((100, 50, 1., None), (100., 50.)),
+            ((200, 100, 2., None), (100., 50.)),
+            ((150, 75, 1.5, None), (100., 50.)),
+            // invalid scale factors are ignored
+            ((100, 50, 0., None), (100., 50.)),
+            ((100, 50, -2., None), (100., 50.)),
+            ((400, 200, 1., Some((100, 100))), (100., 50.)),
+            ((200, 400, 1., Some((100, 100))), (50., 100.)),
+            ((200, 100, 2., Some((50, 50))), (50., 25.)),
+            // small images are not scaled up
+            ((10, 20, 1., Some((100, 100))), (10., 20.)),
+            ((100, 50, 2., Some((100, 100))), (50., 25.)),
+        ] {
+            assert_eq!(
+                logical_size(width, height, scale_factor, max_size),
+                size,
+                "{width}x{height} at {scale_factor}x, max {max_size:?}"
+            );
+        }
+    }
+}
@@ -22,7 +22,7 @@
 //!   let event_loop = tao::event_loop::EventLoop::new();
 //!   let window = tao::window::WindowBuilder::new().build(&event_loop).unwrap();
 //!
-//!   let item = drag::DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap()]);
+//!   let item = drag::DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap().into()]);
 //!   let preview_icon = drag::Image::File("./examples/icon.png".into());
 //!
 //!   drag::start_drag(
@@ -39,7 +39,8 @@
 //!       println!("drag result: {result:?}");
 //!     },
 //!     drag::Options::default(),
-//!   );
+//!   )
+//!   .unwrap();
 //!   ```
 //!
 //!   - wry:
@@ -48,7 +49,7 @@
 //!   let window = wry::application::window::WindowBuilder::new().build(&event_loop).unwrap();
 //!   let webview = wry::webview::WebViewBuilder::new(window).unwrap().build().unwrap();
 //!
-//!   let item = drag::DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap()]);
+//!   let item = drag::DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap().into()]);
 //!   let preview_icon = drag::Image::File("./examples/icon.png".into());
 //!
 //!   drag::start_drag(
@@ -65,7 +66,8 @@
 //!       println!("drag result: {result:?}");
 //!     },
 //!     drag::Options::default(),
-//!   );
+//!   )
+//!   .unwrap();
 //!   ```
 //!
 //!   - winit:
@@ -73,7 +75,7 @@
 //!   let event_loop = winit::event_loop::EventLoop::new().unwrap();
 //!   let window = winit::window::WindowBuilder::new().build(&event_loop).unwrap();
 //!
-//!   let item = drag::DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap()]);
+//!   let item = drag::DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap().into()]);
 //!   let preview_icon = drag::Image::File("./examples/icon.png".into());
 //!
 //!   # #[cfg(not(target_os = "linux"))]
@@ -81,16 +83,42 @@
 //!     println!("drag result: {result:?}");
 //!   }, Default::default());
 //!   ```
+//!
+//! - `drag::start_drag` returns a `drag::DragSession` to query the state of the drag operation or abort it with `DragSession::cancel`.
+//!   Dropping the session lets the drag operation run to completion.
+//!
+//! - Enable the `async` feature to use `drag::start_drag_async`, which can be called from any thread and resolves to the drag outcome.
+//!
+//! - Enable the `image-decode` feature to decode drag images in pure Rust, supporting the same formats on every platform including SVG.
+//!
+//! - Use `drag::DropTarget` to receive files, text and custom data dropped on a window (currently only supported on Linux).
 
 #[cfg(target_os = "macos")]
 #[macro_use]
 extern crate objc;
 
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
+#[cfg(feature = "async")]
+mod future;
+mod html;
+mod image;
+mod path;
 mod platform_impl;
+mod session;
+pub mod uri;
+mod virtual_files;
 
+#[cfg(feature = "async")]
+pub use future::{start_drag_async, DragFuture, DragOutcome};
 pub use platform_impl::start_drag;
+#[cfg(target_os = "linux")]
+pub use platform_impl::DropTarget;
+pub use session::DragSession;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -107,6 +135,13 @@ pub enum Error {
     FailedToStartDrag,
     #[error("drag image not found")]
     ImageNotFound,
+    #[error("failed to decode drag image{}: {message}", path.as_ref().map(|path| format!(" `{}`", path.display())).unwrap_or_default())]
+    ImageDecode {
+        /// The file of an [`Image::File`].
+        path: Option<PathBuf>,
+        /// The error reported by the decoder.
+        message: String,
+    },
     #[cfg(target_os = "linux")]
     #[error("empty drag target list")]
     EmptyTargetList,
@@ -114,40 +149,403 @@ pub enum Error {
     FailedToDrop,
     #[error("failed to get cursor position")]
     FailedToGetCursorPosition,
+    #[error("invalid path `{}`: {source}", path.display())]
+    InvalidPath {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid file URI: {0}")]
+    InvalidFileUri(String),
+    #[error("window unavailable: {0}")]
+    WindowUnavailable(Box<dyn std::error::Error + Send + Sync>),
+    /// The drag operation was torn down without reporting its result.
+    #[cfg(feature = "async")]
+    #[error("the drag operation ended without a result")]
+    DragInterrupted,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum DragResult {
-    Dropped,
-    Cancel,
+    /// The item was dropped and the target performed the given action.
+    Dropped(DragAction),
+    /// The drag operation ended without a drop.
+    Cancel(CancelReason),
+}
+
+/// The reason a drag operation was cancelled.
+///
+/// - **macOS**: Always reported as [`CancelReason::NoTarget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum CancelReason {
+    /// The item was not dropped on a target that accepts it.
+    NoTarget,
+    /// The user cancelled the operation, e.g. by pressing Escape.
+    UserCancelled,
+    /// The drop target did not respond in time.
+    ///
+    /// - **Windows**: Unsupported.
+    TimeoutExpired,
+    /// The pointer grab was taken by another application.
+    ///
+    /// - **Windows**: Unsupported.
+    GrabBroken,
+    /// The operation failed due to an unspecified error.
+    Error,
 }
 
+/// Progress of a drag operation, reported to [`Options::on_event`].
+///
+/// Positions are in screen coordinates, like the position reported when the drag operation ends.
+#[derive(Debug, Clone, Copy)]
+pub enum DragEvent {
+    /// The drag operation started.
+    Started,
+    /// The cursor moved.
+    Moved(CursorPosition),
+    /// The action proposed by the target under the cursor changed.
+    ///
+    /// - **macOS**: Unsupported.
+    ActionChanged(Option<DragAction>),
+    /// The cursor left the window the drag operation started from.
+    LeftSourceWindow,
+    /// The cursor moved back into the window the drag operation started from.
+    ReenteredSourceWindow,
+    /// The drag operation ended.
+    Finished(DragResult),
+}
+
+pub type DragEventHandler = Box<dyn Fn(DragEvent) + Send>;
+
 pub type DataProvider = Box<dyn Fn(&str) -> Option<Vec<u8>>>;
 
+pub type CompositeProvider = Box<dyn Fn(Representation<'_>) -> Option<Vec<u8>>>;
+
+pub type ContentProvider = Box<dyn FnOnce() -> std::io::Result<Vec<u8>> + Send>;
+
+/// A file that does not exist on disk until a drop target asks for it.
+pub struct VirtualFile {
+    /// The file name, shown by the drop target.
+    pub name: String,
+    /// Generates the contents of the file.
+    pub contents: ContentProvider,
+}
+
+impl VirtualFile {
+    pub fn new<F: FnOnce() -> std::io::Result<Vec<u8>> + Send + 'static>(
+        name: impl Into<String>,
+        contents: F,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            contents: Box::new(contents),
+        }
+    }
+}
+
 /// Item to be dragged.
 pub enum DragItem {
     /// A list of files to be dragged.
     ///
-    /// The paths must be absolute.
-    Files(Vec<PathBuf>),
+    /// The files are also offered as plain text with shell-quoted absolute paths, see [`Options::file_text_separator`].
+    ///
+    /// Relative paths are resolved against the current directory.
+    /// [`Error::InvalidPath`] is returned if a file does not exist, see [`Options::follow_symlinks`] for symbolic links.
+    ///
+    /// Files with a [`DragFile::name`] are linked under that name from a temporary directory,
+    /// which is removed according to [`Options::virtual_files_cleanup`] after the drag operation ended.
+    ///
+    /// - **Linux**: When [`DragAction::Move`] is allowed, the files are also offered as a cut selection
+    ///   (`x-special/gnome-copied-files` and `application/x-kde-cutselection`) so GNOME and KDE file managers move them.
+    ///   The result reports [`DragAction::Move`] only when the drop target performed a move. Not offered for renamed files.
+    Files(Vec<DragFile>),
+    /// Files generated on demand.
+    ///
+    /// The files are written to a temporary directory when the drop target first asks for them
+    /// and removed according to [`Options::virtual_files_cleanup`] after the drag operation ended.
+    ///
+    /// - **Windows**: The files are offered as file contents (`CFSTR_FILEDESCRIPTORW` and `CFSTR_FILECONTENTS`),
+    ///   drop targets that only accept file paths are not supported.
+    VirtualFiles(Vec<VirtualFile>),
+    /// A file generated on demand and saved directly to the location the user dropped it on.
+    ///
+    /// Drop targets that support the [XDS protocol](https://freedesktop.org/wiki/Specifications/XDS/)
+    /// choose the destination and the file is written there.
+    /// Other targets receive a temporary copy, like [`DragItem::VirtualFiles`].
+    ///
+    /// - **macOS / Windows**: Unsupported, always dragged as a temporary copy.
+    DirectSave(VirtualFile),
+    /// Plain text, offered as UTF-8 and the platform's text formats.
+    Text(String),
+    /// A list of URIs to be dragged, such as web links or `mailto:`, `sftp://` and `smb://` locations.
+    ///
+    /// Offered as a URI list, a Mozilla URL list including the titles and a plain text fallback.
+    ///
+    /// - **Windows**: Only the first URI is offered as a URL.
+    Uris(Vec<DragUri>),
+    /// Rich text, offered as HTML along with a plain text fallback for targets that do not accept HTML.
+    Html {
+        html: String,
+        /// The plain text fallback. Derived from `html` by stripping the tags when not set.
+        text: Option<String>,
+    },
+    /// The same content in several representations, offered together in a single drag operation.
+    ///
+    /// The drop target picks the representation it supports, see [`Composite::provider`].
+    Composite(Composite),
     /// Data to share with another app.
     ///
+    /// Each entry in `types` is offered as a drag target and the `provider` is called with the type requested by the drop target.
+    ///
     /// - **Windows**: Not supported. Will result in a dummy drag operation of current folder that will be cancelled upon dropping.
-    /// - **Linux (gtk)**: Not supported. Will result in a dummy drag operation that contains nothing to drop.
     Data {
         provider: DataProvider,
         types: Vec<String>,
     },
 }
 
+/// Content offered in several representations by [`DragItem::Composite`],
+/// such as an email message dragged as an `.eml` file, a plain text summary and an app-specific type.
+///
+/// ```
+/// let item = drag::DragItem::Composite(
+///   drag::Composite::new()
+///     .with_files(["/path/to/message.eml"])
+///     .with_text("Meeting notes")
+///     .with_data(vec!["com.example.message".into()], |representation| match representation {
+///       drag::Representation::Data(_) => Some(b"message-id".to_vec()),
+///       _ => None,
+///     }),
+/// );
+/// ```
+pub struct Composite {
+    /// Files, offered as in [`DragItem::Files`].
+    pub files: Vec<DragFile>,
+    /// Plain text. Derived from `html` when not set, or from the paths of `files` as in [`DragItem::Files`].
+    pub text: Option<String>,
+    pub html: Option<String>,
+    /// Custom types, generated by the `provider`.
+    pub types: Vec<String>,
+    /// Called with the representation picked by the drop target.
+    ///
+    /// Returns the data of the custom types, the return value is ignored for the other representations.
+    ///
+    /// - **Windows**: Called for each custom type when the drag operation starts. The other representations are not reported.
+    /// - **macOS**: Only the first file is part of the composite item, the other files are dragged as separate items.
+    pub provider: CompositeProvider,
+}
+
+impl Composite {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            text: None,
+            html: None,
+            types: Vec::new(),
+            provider: Box::new(|_| None),
+        }
+    }
+
+    pub fn with_files(mut self, files: impl IntoIterator<Item = impl Into<DragFile>>) -> Self {
+        self.files = files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text.replace(text.into());
+        self
+    }
+
+    pub fn with_html(mut self, html: impl Into<String>) -> Self {
+        self.html.replace(html.into());
+        self
+    }
+
+    pub fn with_data<F: Fn(Representation<'_>) -> Option<Vec<u8>> + 'static>(
+        mut self,
+        types: Vec<String>,
+        provider: F,
+    ) -> Self {
+        self.types = types;
+        self.provider = Box::new(provider);
+        self
+    }
+}
+
+impl Default for Composite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A representation of a [`Composite`] item picked by the drop target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation<'a> {
+    Files,
+    Text,
+    Html,
+    /// One of the custom [`Composite::types`].
+    Data(&'a str),
+}
+
+/// A file to be dragged with [`DragItem::Files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct DragFile {
+    pub path: PathBuf,
+    /// The file name seen by the user and the drop target, when it differs from the name on disk.
+    ///
+    /// The file is hard linked under this name, or symbolically linked if it is on another file system.
+    pub name: Option<String>,
+}
+
+impl DragFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            name: None,
+        }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name.replace(name.into());
+        self
+    }
+}
+
+impl From<PathBuf> for DragFile {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<&Path> for DragFile {
+    fn from(path: &Path) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<&str> for DragFile {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+/// A URI to be dragged with [`DragItem::Uris`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct DragUri {
+    pub uri: String,
+    /// Title of the link, shown by browsers and file managers when it is dropped.
+    pub title: Option<String>,
+}
+
+impl DragUri {
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            title: None,
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title.replace(title.into());
+        self
+    }
+
+    /// The title if set, otherwise the URI.
+    #[cfg(not(windows))]
+    pub(crate) fn title_or_uri(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.uri)
+    }
+}
+
+impl From<String> for DragUri {
+    fn from(uri: String) -> Self {
+        Self::new(uri)
+    }
+}
+
+impl From<&str> for DragUri {
+    fn from(uri: &str) -> Self {
+        Self::new(uri)
+    }
+}
+
 #[derive(Default)]
 pub struct Options {
     pub skip_animatation_on_cancel_or_failure: bool,
+    /// The actions the drop target is allowed to perform.
+    ///
+    /// An empty list only allows [`DragAction::Copy`].
+    pub allowed_actions: Vec<DragAction>,
+    /// Handler for the progress events of the drag operation.
+    pub on_event: Option<DragEventHandler>,
+    /// Whether symbolic links in [`DragItem::Files`] are resolved to their target.
+    ///
+    /// When disabled, the link itself is dragged.
+    pub follow_symlinks: bool,
+    /// Separator of the shell-quoted paths offered as plain text by [`DragItem::Files`].
+    ///
+    /// - **macOS**: Not supported, each file is offered as its own item.
+    pub file_text_separator: FileTextSeparator,
+    /// When the files of [`DragItem::VirtualFiles`] and the links to renamed [`DragItem::Files`] are removed.
+    pub virtual_files_cleanup: CleanupPolicy,
+    /// Whether the drag operation fails to start with [`Error::ImageNotFound`] or [`Error::ImageDecode`]
+    /// when the drag image can not be loaded.
+    ///
+    /// When disabled, the images that fail to load are left out of the drag image.
+    pub strict_image: bool,
+}
+
+/// When the files written for a drag operation are removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupPolicy {
+    /// Remove the files as soon as the drag operation ends.
+    ///
+    /// The drop target might not have finished reading the files yet.
+    Immediately,
+    /// Remove the files once the delay elapsed after the drag operation ended, giving the drop target time to copy them.
+    After(Duration),
+    /// Keep the files in the temporary directory.
+    Never,
+}
+
+impl Default for CleanupPolicy {
+    fn default() -> Self {
+        Self::After(Duration::from_secs(10))
+    }
+}
+
+/// Separator of the shell-quoted paths offered as plain text by [`DragItem::Files`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum FileTextSeparator {
+    /// One path per line.
+    #[default]
+    Newline,
+    /// Paths separated by spaces, as expected on a shell command line.
+    Space,
+}
+
+impl Options {
+    pub(crate) fn allowed_actions(&self) -> &[DragAction] {
+        if self.allowed_actions.is_empty() {
+            &[DragAction::Copy]
+        } else {
+            &self.allowed_actions
+        }
+    }
 }
 
 /// An image definition.
-#[derive(Debug)]
+///
+/// [`Image::File`] and [`Image::Raw`] are decoded by the platform: gdk-pixbuf on Linux, WIC on Windows and NSImage on macOS.
+/// With the `image-decode` feature, they are decoded the same way on every platform instead,
+/// supporting PNG, JPEG, GIF, WebP, BMP, ICO, TIFF and SVG. SVG images are rasterized at the size they are shown,
+/// see [`DragImage::scale_factor`] and [`DragImage::max_size`].
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[cfg_attr(feature = "serde", serde(untagged))]
 pub enum Image {
@@ -155,12 +553,247 @@ pub enum Image {
     File(PathBuf),
     /// Raw bytes of the image.
     Raw(Vec<u8>),
+    /// Pixels rendered in memory, used without encoding and decoding them.
+    ///
+    /// The buffer holds `height` rows of `stride` bytes, each starting with `width` pixels
+    /// of 8-bit red, green, blue and alpha channels. The alpha is not premultiplied.
+    Rgba {
+        width: u32,
+        height: u32,
+        stride: u32,
+        pixels: Vec<u8>,
+    },
+    /// One preview per dragged item, combined into a stacked drag image.
+    ///
+    /// - **Linux**: The first previews are stacked, with a badge showing the number of items when there are more.
+    /// - **macOS**: Each dragged item gets its own preview, the last one is used for the remaining items.
+    /// - **Windows**: Only the first preview is shown.
+    Stack(Vec<Image>),
+    /// Pixels decoded ahead of time by [`Image::prepare`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Prepared(PreparedImage),
+    /// Previews generated from the files of [`DragItem::Files`] and [`DragItem::Composite`]:
+    /// a thumbnail for image files and the icon of the file type for the other files,
+    /// stacked as in [`Image::Stack`] when several files are dragged.
+    ///
+    /// The previews are [`DragImage::max_size`] large, or 96 logical pixels when it is not set.
+    /// Dragging other items shows no image.
+    ///
+    /// - **Linux**: The icons come from the icon theme, by the content type GIO detects for the files.
+    /// - **macOS**: The icons come from NSWorkspace.
+    /// - **Windows**: The thumbnails and icons are generated by the shell, as shown by the File Explorer.
+    Auto,
+}
+
+impl Image {
+    /// Loads the image the way the drag operation does, returning the error reported in [`Options::strict_image`] mode.
+    ///
+    /// ```rust,no_run
+    /// drag::Image::File("./examples/icon.png".into())
+    ///     .validate()
+    ///     .expect("broken drag preview");
+    /// ```
+    pub fn validate(&self) -> Result<()> {
+        image::validate(self)
+    }
+
+    /// Decodes the image to pixels that are shared by its clones, so dragging it again doesn't decode it again.
+    ///
+    /// This function can be called from any thread, e.g. to decode the previews of a list view in the background.
+    /// With the `image-decode` feature, SVG images are rasterized at their own size.
+    ///
+    /// ```rust,no_run
+    /// let icon = drag::Image::File("./examples/icon.png".into())
+    ///     .prepare()
+    ///     .expect("broken drag preview");
+    /// // every drag operation reuses the decoded pixels
+    /// let preview_icon = icon.clone();
+    /// ```
+    pub fn prepare(self) -> Result<Image> {
+        image::prepare(self)
+    }
+
+    /// The previews of the dragged items, with nested stacks flattened.
+    pub(crate) fn into_previews(self) -> Vec<Image> {
+        match self {
+            Image::Stack(images) => images.into_iter().flat_map(Image::into_previews).collect(),
+            image => vec![image],
+        }
+    }
+}
+
+/// An image decoded to pixels by [`Image::prepare`].
+///
+/// Cloning it shares the pixels.
+#[derive(Debug, Clone)]
+pub struct PreparedImage {
+    width: u32,
+    height: u32,
+    /// Rows of `width` RGBA pixels, the alpha is not premultiplied.
+    pixels: Arc<[u8]>,
+}
+
+impl PreparedImage {
+    /// Copies the pixels of a valid [`Image::Rgba`] buffer.
+    pub(crate) fn from_rgba(width: u32, height: u32, stride: u32, pixels: &[u8]) -> Self {
+        let row_len = width as usize * 4;
+        Self {
+            width,
+            height,
+            pixels: pixels
+                .chunks(stride as usize)
+                .take(height as usize)
+                .flat_map(|row| &row[..row_len])
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// The width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The length in bytes of a row of pixels.
+    pub(crate) fn stride(&self) -> u32 {
+        self.width * 4
+    }
+
+    /// The pixels, shared with the clones of the image.
+    pub(crate) fn pixels(&self) -> &Arc<[u8]> {
+        &self.pixels
+    }
+}
+
+/// The image shown under the cursor during a drag operation.
+///
+/// ```rust
+/// let image = drag::DragImage::new(drag::Image::File("./examples/icon.png".into()))
+///     // the user grabbed the element 12px from its left edge and 8px from its top edge
+///     .with_hotspot(12, 8)
+///     // the image was rendered at twice the logical size
+///     .with_scale_factor(2.0)
+///     .with_max_size(128, 128)
+///     .with_opacity(0.8);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DragImage {
+    /// The image to show.
+    pub image: Image,
+    /// The point of the image under the cursor, in logical pixels from its top-left corner.
+    ///
+    /// When not set, the image is anchored at its top-left corner on Linux and Windows,
+    /// and centered under the cursor on macOS.
+    pub hotspot: Option<(i32, i32)>,
+    /// The number of image pixels per logical pixel, e.g. `2.0` for an image rendered for a HiDPI display.
+    pub scale_factor: f64,
+    /// The maximum logical size of the image, larger images are scaled down keeping their aspect ratio.
+    pub max_size: Option<(u32, u32)>,
+    /// The opacity of the image, from `0.0` (invisible) to `1.0` (opaque).
+    pub opacity: f64,
+}
+
+impl DragImage {
+    pub fn new(image: Image) -> Self {
+        Self {
+            image,
+            hotspot: None,
+            scale_factor: 1.0,
+            max_size: None,
+            opacity: 1.0,
+        }
+    }
+
+    /// Places the given point of the image, in logical pixels from its top-left corner, under the cursor.
+    pub fn with_hotspot(mut self, x: i32, y: i32) -> Self {
+        self.hotspot.replace((x, y));
+        self
+    }
+
+    /// Sets the number of image pixels per logical pixel.
+    pub fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    /// Scales the image down to fit the given logical size.
+    pub fn with_max_size(mut self, width: u32, height: u32) -> Self {
+        self.max_size.replace((width, height));
+        self
+    }
+
+    /// Sets the opacity of the image, from `0.0` (invisible) to `1.0` (opaque).
+    pub fn with_opacity(mut self, opacity: f64) -> Self {
+        self.opacity = opacity;
+        self
+    }
+}
+
+impl From<Image> for DragImage {
+    fn from(image: Image) -> Self {
+        Self::new(image)
+    }
+}
+
+/// Action of a drag and drop operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum DragAction {
+    /// The data is copied to the drop target.
+    Copy,
+    /// The data is moved to the drop target, the source should delete it.
+    Move,
+    /// The drop target creates a link to the data.
+    Link,
+}
+
+/// Data received by a [`DropTarget`].
+#[derive(Debug)]
+pub enum DropData {
+    /// A list of local files.
+    Files(Vec<PathBuf>),
+    /// Plain text.
+    Text(String),
+    /// Data of one of the custom types the drop target accepts.
+    Data { data_type: String, data: Vec<u8> },
+}
+
+/// Event emitted by a [`DropTarget`].
+///
+/// Positions are relative to the window the drop target is attached to.
+#[derive(Debug)]
+pub enum DropEvent {
+    /// A drag operation entered the window.
+    Enter {
+        position: CursorPosition,
+        /// The types offered by the drag source.
+        types: Vec<String>,
+        action: Option<DragAction>,
+    },
+    /// The cursor moved over the window.
+    Over {
+        position: CursorPosition,
+        action: Option<DragAction>,
+    },
+    /// The drag operation left the window without dropping.
+    Leave,
+    /// The drag operation was dropped on the window.
+    Drop {
+        position: CursorPosition,
+        data: DropData,
+        action: Option<DragAction>,
+    },
 }
 
 /// Logical position of the cursor.
 ///
 /// - **Windows**: Currently the win32 API for logical position reports physical position as well, due to the complicated nature of potential multiple monitor with different scaling there's no trivial solution to be incorporated.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct CursorPosition {
     pub x: i32,
@@ -0,0 +1,211 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[cfg(not(target_os = "macos"))]
+use crate::FileTextSeparator;
+use crate::{virtual_files::VirtualFileStore, Composite, DragFile, DragItem, Error, Options};
+
+/// Checks that the dragged files exist and makes their paths absolute.
+///
+/// Relative paths are resolved against the current directory. Symbolic links are resolved to their target
+/// with [`Options::follow_symlinks`], otherwise only the parent directories are resolved and the link itself is dragged.
+pub(crate) fn normalize_item(item: DragItem, options: &Options) -> crate::Result<DragItem> {
+    match item {
+        DragItem::Files(files) => {
+            normalize_files(files, options.follow_symlinks).map(DragItem::Files)
+        }
+        DragItem::Composite(composite) => {
+            let files = normalize_files(composite.files, options.follow_symlinks)?;
+            Ok(DragItem::Composite(Composite { files, ..composite }))
+        }
+        item => Ok(item),
+    }
+}
+
+fn normalize_files(files: Vec<DragFile>, follow_symlinks: bool) -> crate::Result<Vec<DragFile>> {
+    files
+        .into_iter()
+        .map(|file| match normalize_path(&file.path, follow_symlinks) {
+            Ok(path) => Ok(DragFile { path, ..file }),
+            Err(source) => Err(Error::InvalidPath {
+                path: file.path,
+                source,
+            }),
+        })
+        .collect()
+}
+
+/// The paths to drag, linking the files that have a display name from a temporary directory.
+///
+/// The returned store holds the links and must be cleaned up once the drag operation ended.
+pub(crate) fn display_paths(
+    files: Vec<DragFile>,
+) -> crate::Result<(Vec<PathBuf>, Option<Arc<VirtualFileStore>>)> {
+    if files.iter().all(|file| file.name.is_none()) {
+        return Ok((files.into_iter().map(|file| file.path).collect(), None));
+    }
+
+    let store = VirtualFileStore::new(Vec::new());
+    let paths = files
+        .into_iter()
+        .enumerate()
+        .map(|(index, file)| match file.name {
+            Some(name) => {
+                store
+                    .link(index, &file.path, &name)
+                    .map_err(|source| Error::InvalidPath {
+                        path: file.path,
+                        source,
+                    })
+            }
+            None => Ok(file.path),
+        })
+        .collect::<crate::Result<_>>()?;
+    Ok((paths, Some(store)))
+}
+
+fn normalize_path(path: &Path, follow_symlinks: bool) -> io::Result<PathBuf> {
+    if follow_symlinks {
+        return canonicalize(path);
+    }
+
+    // fails if the file does not exist, without following the link
+    std::fs::symlink_metadata(path)?;
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                std::env::current_dir()?
+            } else {
+                canonicalize(parent)?
+            };
+            Ok(parent.join(file_name))
+        }
+        _ => canonicalize(path),
+    }
+}
+
+fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    return dunce::canonicalize(path);
+    #[cfg(not(windows))]
+    return std::fs::canonicalize(path);
+}
+
+/// The plain text representation of the dragged files, with shell-quoted paths.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn shell_quoted_list(paths: &[PathBuf], separator: FileTextSeparator) -> String {
+    let separator = match separator {
+        FileTextSeparator::Newline => "\n",
+        FileTextSeparator::Space => " ",
+    };
+    paths
+        .iter()
+        .map(|path| shell_quote(&path.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Quotes the path for a POSIX shell, wrapping it in single quotes if it contains special characters.
+#[cfg(not(windows))]
+pub(crate) fn shell_quote(path: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./,:+@%=".contains(c);
+    if !path.is_empty() && path.chars().all(is_safe) {
+        path.to_string()
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
+
+/// Quotes the path for the Windows command line, wrapping it in double quotes if it contains special characters.
+#[cfg(windows)]
+pub(crate) fn shell_quote(path: &str) -> String {
+    if !path.is_empty()
+        && !path.contains(|c: char| c.is_whitespace() || "&()[]{}^=;!'+,`~".contains(c))
+    {
+        path.to_string()
+    } else {
+        // double quotes are not allowed in Windows paths
+        format!("\"{path}\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shell_quote;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn posix_shell_quote() {
+        for (path, quoted) in [
+            ("/home/user/file.txt", "/home/user/file.txt"),
+            ("/a-b_c/d,e:f+g@h%i=j", "/a-b_c/d,e:f+g@h%i=j"),
+            ("", "''"),
+            ("/with space", "'/with space'"),
+            ("/it's", r"'/it'\''s'"),
+            ("''", r"''\'''\'''"),
+            ("/$HOME/*.txt", "'/$HOME/*.txt'"),
+            ("/a;b&c|d", "'/a;b&c|d'"),
+            ("/back\\slash", "'/back\\slash'"),
+            ("/new\nline", "'/new\nline'"),
+            ("/résumé", "'/résumé'"),
+        ] {
+            assert_eq!(shell_quote(path), quoted, "{path:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_shell_quote() {
+        for (path, quoted) in [
+            (r"C:\Users\me\file.txt", r"C:\Users\me\file.txt"),
+            (r"\\server\share\file", r"\\server\share\file"),
+            ("", "\"\""),
+            (r"C:\Program Files\app", r#""C:\Program Files\app""#),
+            (r"C:\a&b", r#""C:\a&b""#),
+            (r"C:\a(1)", r#""C:\a(1)""#),
+            (r"C:\a^b", r#""C:\a^b""#),
+            (r"C:\it's", r#""C:\it's""#),
+            (r"C:\a=b;c", r#""C:\a=b;c""#),
+        ] {
+            assert_eq!(shell_quote(path), quoted, "{path:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn shell_quoted_list() {
+        use crate::FileTextSeparator;
+        use std::path::PathBuf;
+
+        #[cfg(not(windows))]
+        let (paths, newline, space) = (
+            [PathBuf::from("/a"), PathBuf::from("/b c")],
+            "/a\n'/b c'",
+            "/a '/b c'",
+        );
+        #[cfg(windows)]
+        let (paths, newline, space) = (
+            [PathBuf::from(r"C:\a"), PathBuf::from(r"C:\b c")],
+            "C:\\a\n\"C:\\b c\"",
+            "C:\\a \"C:\\b c\"",
+        );
+
+        assert_eq!(
+            super::shell_quoted_list(&paths, FileTextSeparator::Newline),
+            newline
+        );
+        assert_eq!(
+            super::shell_quoted_list(&paths, FileTextSeparator::Space),
+            space
+        );
+        assert_eq!(super::shell_quoted_list(&[], FileTextSeparator::Space), "");
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use super::drag_action_from_gdk;
+use crate::{CursorPosition, DropData, DropEvent};
+use gdkx11::{
+    gdk,
+    glib::{self, ObjectExt, SignalHandlerId},
+};
+use gtk::prelude::{DragContextExtManual, WidgetExt, WidgetExtManual};
+use std::{cell::Cell, rc::Rc};
+
+const INFO_DATA: u32 = 0;
+const INFO_URI: u32 = 1;
+const INFO_TEXT: u32 = 2;
+
+/// Receives drag operations dropped on a window.
+///
+/// Files, text and the custom types given to [`DropTarget::new`] are accepted.
+/// The window stops being a drop target when this value is dropped.
+pub struct DropTarget {
+    window: gtk::ApplicationWindow,
+    handler_ids: Vec<SignalHandlerId>,
+}
+
+impl DropTarget {
+    /// Makes the window a drop target, reporting the drag and drop events to the handler.
+    ///
+    /// `types` lists the custom data types accepted by the window, which take precedence over files and text.
+    pub fn new<F: Fn(DropEvent) + 'static>(
+        window: &gtk::ApplicationWindow,
+        types: Vec<String>,
+        handler: F,
+    ) -> Self {
+        let handler = Rc::new(handler);
+        let entered = Rc::new(Cell::new(false));
+        let dropping = Rc::new(Cell::new(false));
+
+        let target_list = gtk::TargetList::new(&[]);
+        for data_type in &types {
+            target_list.add(&gdk::Atom::intern(data_type), 0, INFO_DATA);
+        }
+        target_list.add_uri_targets(INFO_URI);
+        target_list.add_text_targets(INFO_TEXT);
+
+        window.drag_dest_set(
+            gtk::DestDefaults::empty(),
+            &[],
+            gdk::DragAction::COPY | gdk::DragAction::MOVE | gdk::DragAction::LINK,
+        );
+        window.drag_dest_set_target_list(Some(&target_list));
+
+        let mut handler_ids = Vec::new();
+
+        let handler_ = handler.clone();
+        let entered_ = entered.clone();
+        handler_ids.push(
+            window.connect_drag_motion(move |window, context, x, y, time| {
+                // refuse drags offering none of the accepted targets, they would be rejected on drop
+                if window.drag_dest_find_target(context, None).is_none() {
+                    context.drag_status(gdk::DragAction::empty(), time);
+                    return false;
+                }
+
+                let position = CursorPosition { x, y };
+                let action = drag_action_from_gdk(context.suggested_action());
+                if !entered_.replace(true) {
+                    handler_(DropEvent::Enter {
+                        position,
+                        types: context
+                            .list_targets()
+                            .iter()
+                            .map(|atom| atom.name().to_string())
+                            .collect(),
+                        action,
+                    });
+                } else {
+                    handler_(DropEvent::Over { position, action });
+                }
+                context.drag_status(context.suggested_action(), time);
+                true
+            }),
+        );
+
+        // GTK emits drag-leave right before drag-drop, so the event is deferred to the next
+        // main loop iteration and skipped if a drop started in the meantime.
+        let handler_ = handler.clone();
+        let entered_ = entered.clone();
+        let dropping_ = dropping.clone();
+        handler_ids.push(window.connect_drag_leave(move |_, _, _| {
+            entered_.set(false);
+            let handler = handler_.clone();
+            let dropping = dropping_.clone();
+            glib::idle_add_local_once(move || {
+                if !dropping.get() {
+                    handler(DropEvent::Leave);
+                }
+            });
+        }));
+
+        let dropping_ = dropping.clone();
+        handler_ids.push(
+            window.connect_drag_drop(move |window, context, _, _, time| {
+                match window.drag_dest_find_target(context, None) {
+                    Some(target) => {
+                        dropping_.set(true);
+                        window.drag_get_data(context, &target, time);
+                        true
+                    }
+                    None => false,
+                }
+            }),
+        );
+
+        handler_ids.push(window.connect_drag_data_received(
+            move |window, context, x, y, selection_data, info, time| {
+                dropping.set(false);
+                entered.set(false);
+
+                let data = match info {
+                    INFO_URI => {
+                        // only files on this machine can be opened by their path
+                        let paths: Vec<_> = selection_data
+                            .uris()
+                            .iter()
+                            .filter_map(|uri| crate::uri::to_path(uri).ok())
+                            .filter_map(|(path, hostname)| hostname.is_none().then_some(path))
+                            .collect();
+                        if paths.is_empty() {
+                            // the URIs are remote or not files, read them as text if the source offers it
+                            let text_targets = gtk::TargetList::new(&[]);
+                            text_targets.add_text_targets(INFO_TEXT);
+                            if let Some(target) =
+                                window.drag_dest_find_target(context, Some(&text_targets))
+                            {
+                                window.drag_get_data(context, &target, time);
+                                return;
+                            }
+                            None
+                        } else {
+                            Some(DropData::Files(paths))
+                        }
+                    }
+                    INFO_TEXT => selection_data
+                        .text()
+                        .map(|text| DropData::Text(text.to_string())),
+                    _ => Some(DropData::Data {
+                        data_type: selection_data.target().name().to_string(),
+                        data: selection_data.data(),
+                    }),
+                };
+
+                let success = data.is_some();
+                if let Some(data) = data {
+                    handler(DropEvent::Drop {
+                        position: CursorPosition { x, y },
+                        data,
+                        action: drag_action_from_gdk(context.selected_action()),
+                    });
+                }
+                context.drag_finish(
+                    success,
+                    success && context.selected_action() == gdk::DragAction::MOVE,
+                    time,
+                );
+            },
+        ));
+
+        Self {
+            window: window.clone(),
+            handler_ids,
+        }
+    }
+}
+
+impl Drop for DropTarget {
+    fn drop(&mut self) {
+        for handler_id in self.handler_ids.drain(..) {
+            self.window.disconnect(handler_id);
+        }
+        self.window.drag_dest_unset();
+    }
+}
@@ -2,95 +2,662 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use crate::{CursorPosition, DragItem, DragResult, Error, Image, Options};
+use crate::{
+    session::DragEmitter, virtual_files::VirtualFileStore, CancelReason, Composite, CursorPosition,
+    DragAction, DragEvent, DragImage, DragItem, DragResult, DragSession, Error, FileTextSeparator,
+    Image, Options, Representation,
+};
 use gdkx11::{
-    gdk,
-    glib::{ObjectExt, SignalHandlerId},
+    gdk::{self, prelude::GdkContextExt},
+    glib::{self, ObjectExt, SignalHandlerId},
 };
 use gtk::{
-    gdk_pixbuf,
-    prelude::{DragContextExtManual, PixbufLoaderExt, WidgetExt, WidgetExtManual},
+    cairo, gdk_pixbuf, gio,
+    prelude::{
+        DragContextExtManual, FileExt, IconThemeExt, PixbufLoaderExt, WidgetExt, WidgetExtManual,
+    },
     Inhibit,
 };
 use std::{
+    path::{Path, PathBuf},
     rc::Rc,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+mod drop_target;
+
+pub use drop_target::DropTarget;
+
+const INFO_TEXT: u32 = 0;
+const INFO_HTML: u32 = 1;
+const INFO_URI: u32 = 2;
+const INFO_MOZ_URL: u32 = 3;
+const INFO_DIRECT_SAVE: u32 = 4;
+const INFO_OCTET_STREAM: u32 = 5;
+const INFO_GNOME_COPIED_FILES: u32 = 6;
+const INFO_KDE_CUT_SELECTION: u32 = 7;
+const INFO_DATA: u32 = 8;
+
+/// Number of previews stacked in the drag image of an [`Image::Stack`].
+const MAX_STACKED_PREVIEWS: usize = 3;
+/// Offset between the stacked previews, in pixels.
+const STACK_OFFSET: i32 = 8;
+const BADGE_RADIUS: f64 = 11.;
+
+/// Target and property of the [XDS protocol](https://freedesktop.org/wiki/Specifications/XDS/).
+const XDS_ATOM: &str = "XdndDirectSave0";
+
 pub fn start_drag<F: Fn(DragResult, CursorPosition) + Send + 'static>(
     window: &gtk::ApplicationWindow,
     item: DragItem,
-    image: Image,
+    image: impl Into<DragImage>,
     on_drop_callback: F,
-    options: Options,
-) -> crate::Result<()> {
+    mut options: Options,
+) -> crate::Result<DragSession> {
+    let item = crate::path::normalize_item(item, &options)?;
+    let DragImage {
+        image,
+        hotspot,
+        scale_factor,
+        max_size,
+        opacity,
+    } = crate::image::resolve(image.into(), &item, options.strict_image)?;
+    let pixbuf = drag_pixbuf(image, options.strict_image)?;
     let handler_ids: Arc<Mutex<Vec<SignalHandlerId>>> = Arc::new(Mutex::new(vec![]));
+    let actions = options
+        .allowed_actions()
+        .iter()
+        .fold(gdk::DragAction::empty(), |actions, action| {
+            actions | drag_action_to_gdk(*action)
+        });
 
-    window.drag_source_set(gdk::ModifierType::BUTTON1_MASK, &[], gdk::DragAction::COPY);
-
+    let target_list = gtk::TargetList::new(&[]);
+    let mut virtual_files = None;
+    let mut direct_save_name = None;
     match item {
-        DragItem::Files(paths) => {
-            window.drag_source_add_uri_targets();
+        DragItem::Files(files) => {
+            let (paths, links) = crate::path::display_paths(files)?;
+            // moving a link would leave the original file in place
+            let offer_cut =
+                links.is_none() && options.allowed_actions().contains(&DragAction::Move);
+            virtual_files = links;
+            target_list.add_uri_targets(INFO_URI);
+            target_list.add_text_targets(INFO_TEXT);
+            if offer_cut {
+                target_list.add(
+                    &gdk::Atom::intern("x-special/gnome-copied-files"),
+                    0,
+                    INFO_GNOME_COPIED_FILES,
+                );
+                target_list.add(
+                    &gdk::Atom::intern("application/x-kde-cutselection"),
+                    0,
+                    INFO_KDE_CUT_SELECTION,
+                );
+            }
+            let separator = options.file_text_separator;
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(
+                    window.connect_drag_data_get(move |_, _, data, info, _| match info {
+                        INFO_GNOME_COPIED_FILES => {
+                            let uris: Vec<String> = paths
+                                .iter()
+                                .filter_map(|path| crate::uri::from_path(path, None).ok())
+                                .collect();
+                            let selection = format!("cut\n{}", uris.join("\n"));
+                            data.set(&data.target(), 8, selection.as_bytes());
+                        }
+                        INFO_KDE_CUT_SELECTION => {
+                            data.set(&data.target(), 8, b"1");
+                        }
+                        _ => set_files_data(data, info, &paths, separator),
+                    }),
+                );
+        }
+        DragItem::VirtualFiles(files) => {
+            let store = VirtualFileStore::new(files);
+            target_list.add_uri_targets(INFO_URI);
+            target_list.add_text_targets(INFO_TEXT);
+            let separator = options.file_text_separator;
+            let store_ = store.clone();
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(window.connect_drag_data_get(move |_, _, data, info, _| {
+                    set_files_data(data, info, &store_.materialize_all(), separator);
+                }));
+            virtual_files.replace(store);
+        }
+        DragItem::DirectSave(file) => {
+            let store = VirtualFileStore::new(vec![file]);
+            // validated before any handler is connected
+            direct_save_name.replace(store.file_name(0)?);
+            target_list.add(&gdk::Atom::intern(XDS_ATOM), 0, INFO_DIRECT_SAVE);
+            target_list.add(
+                &gdk::Atom::intern("application/octet-stream"),
+                0,
+                INFO_OCTET_STREAM,
+            );
+            target_list.add_uri_targets(INFO_URI);
+            target_list.add_text_targets(INFO_TEXT);
+            let separator = options.file_text_separator;
+            let store_ = store.clone();
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(
+                    window.connect_drag_data_get(move |_, context, data, info, _| match info {
+                        INFO_DIRECT_SAVE => {
+                            data.set(&data.target(), 8, direct_save(context, &store_));
+                        }
+                        // requested by XDS targets when the file could not be saved to their location
+                        INFO_OCTET_STREAM => {
+                            if let Ok(bytes) = store_.materialize(0).and_then(std::fs::read) {
+                                data.set(&data.target(), 8, &bytes);
+                            }
+                        }
+                        _ => set_files_data(data, info, &store_.materialize_all(), separator),
+                    }),
+                );
+            virtual_files.replace(store);
+        }
+        DragItem::Text(text) => {
+            target_list.add_text_targets(INFO_TEXT);
             handler_ids
                 .lock()
                 .unwrap()
                 .push(window.connect_drag_data_get(move |_, _, data, _, _| {
-                    let uris: Vec<String> = paths
-                        .iter()
-                        .map(|path| format!("file://{}", path.display()))
-                        .collect();
-                    let uris: Vec<&str> = uris.iter().map(|s| s.as_str()).collect();
-                    data.set_uris(&uris);
+                    data.set_text(&text);
                 }));
         }
-        DragItem::Data { .. } => {
-            // Currently leaving it as is as we can utilize it as a dummy dragging feature
-            // on_drop_callback(DragResult::Cancel, get_cursor_position(window).unwrap());
-            // return Ok(());
+        DragItem::Uris(uris) => {
+            target_list.add_uri_targets(INFO_URI);
+            target_list.add(&gdk::Atom::intern("text/x-moz-url"), 0, INFO_MOZ_URL);
+            target_list.add_text_targets(INFO_TEXT);
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(
+                    window.connect_drag_data_get(move |_, _, data, info, _| match info {
+                        INFO_URI => {
+                            let uris: Vec<&str> = uris.iter().map(|uri| uri.uri.as_str()).collect();
+                            data.set_uris(&uris);
+                        }
+                        INFO_MOZ_URL => {
+                            // Mozilla URLs are encoded as UTF-16
+                            let bytes: Vec<u8> = crate::uri::moz_url_list(&uris)
+                                .encode_utf16()
+                                .flat_map(u16::to_ne_bytes)
+                                .collect();
+                            data.set(&data.target(), 16, &bytes);
+                        }
+                        _ => {
+                            data.set_text(&crate::uri::text_list(&uris));
+                        }
+                    }),
+                );
+        }
+        DragItem::Html { html, text } => {
+            let text = text.unwrap_or_else(|| crate::html::to_plain_text(&html));
+            target_list.add(&gdk::Atom::intern("text/html"), 0, INFO_HTML);
+            target_list.add_text_targets(INFO_TEXT);
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(window.connect_drag_data_get(move |_, _, data, info, _| {
+                    if info == INFO_HTML {
+                        data.set(&data.target(), 8, html.as_bytes());
+                    } else {
+                        data.set_text(&text);
+                    }
+                }));
+        }
+        DragItem::Composite(Composite {
+            files,
+            text,
+            html,
+            types,
+            provider,
+        }) => {
+            let (paths, links) = crate::path::display_paths(files)?;
+            virtual_files = links;
+            let text = text
+                .or_else(|| html.as_deref().map(crate::html::to_plain_text))
+                .or_else(|| {
+                    (!paths.is_empty()).then(|| {
+                        crate::path::shell_quoted_list(&paths, options.file_text_separator)
+                    })
+                });
+            // the custom types are the most specific representations
+            for data_type in &types {
+                target_list.add(&gdk::Atom::intern(data_type), 0, INFO_DATA);
+            }
+            if !paths.is_empty() {
+                target_list.add_uri_targets(INFO_URI);
+            }
+            if html.is_some() {
+                target_list.add(&gdk::Atom::intern("text/html"), 0, INFO_HTML);
+            }
+            if text.is_some() {
+                target_list.add_text_targets(INFO_TEXT);
+            }
+            let separator = options.file_text_separator;
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(
+                    window.connect_drag_data_get(move |_, _, data, info, _| match info {
+                        INFO_URI => {
+                            provider(Representation::Files);
+                            set_files_data(data, info, &paths, separator);
+                        }
+                        INFO_HTML => {
+                            if let Some(html) = &html {
+                                provider(Representation::Html);
+                                data.set(&data.target(), 8, html.as_bytes());
+                            }
+                        }
+                        INFO_TEXT => {
+                            if let Some(text) = &text {
+                                provider(Representation::Text);
+                                data.set_text(text);
+                            }
+                        }
+                        _ => {
+                            let target = data.target();
+                            if let Some(bytes) = provider(Representation::Data(&target.name())) {
+                                data.set(&target, 8, &bytes);
+                            }
+                        }
+                    }),
+                );
+        }
+        DragItem::Data { provider, types } => {
+            for data_type in &types {
+                target_list.add(&gdk::Atom::intern(data_type), 0, INFO_DATA);
+            }
+            handler_ids
+                .lock()
+                .unwrap()
+                .push(window.connect_drag_data_get(move |_, _, data, _, _| {
+                    let target = data.target();
+                    if let Some(bytes) = provider(&target.name()) {
+                        data.set(&target, 8, &bytes);
+                    }
+                }));
         }
     }
 
+    // only set once the item was prepared, so failing to prepare it leaves the window untouched
+    window.drag_source_set(gdk::ModifierType::BUTTON1_MASK, &[], actions);
+    window.drag_source_set_target_list(Some(&target_list));
+
     if let Some(target_list) = &window.drag_source_get_target_list() {
         if let Some(drag_context) = window.drag_begin_with_coordinates(
             target_list,
-            gdk::DragAction::COPY,
+            actions,
             gdk::ffi::GDK_BUTTON1_MASK as i32,
             None,
             -1,
             -1,
         ) {
-            let callback = Rc::new(on_drop_callback);
+            let emitter = Rc::new(DragEmitter::new(options.on_event.take()));
+            if let Some(store) = virtual_files {
+                let policy = options.virtual_files_cleanup;
+                emitter.on_finished(Box::new(move || store.cleanup(policy)));
+            }
+            if let Some(name) = direct_save_name {
+                // the drop target replaces the file name with the URI of the file to save
+                let source_window = drag_context.source_window();
+                let xds_atom = gdk::Atom::intern(XDS_ATOM);
+                gdk::property_change(
+                    &source_window,
+                    &xds_atom,
+                    &gdk::Atom::intern("text/plain"),
+                    8,
+                    gdk::PropMode::Replace,
+                    gdk::ChangeData::UChars(name.as_bytes()),
+                );
+                emitter.on_finished(Box::new(move || {
+                    gdk::property_delete(&source_window, &xds_atom)
+                }));
+            }
+
+            let emitter_ = emitter.clone();
+            let callback = Rc::new(move |result, cursor_position| {
+                let result = match result {
+                    DragResult::Cancel(_) if emitter_.is_cancelled() => {
+                        DragResult::Cancel(CancelReason::UserCancelled)
+                    }
+                    result => result,
+                };
+                emitter_.emit(DragEvent::Finished(result));
+                on_drop_callback(result, cursor_position);
+            });
             on_drop_failed(callback.clone(), window, &handler_ids, &options);
-            on_drop_performed(callback.clone(), window, &handler_ids, &drag_context);
-
-            let icon_pixbuf: Option<gdk_pixbuf::Pixbuf> = match &image {
-                Image::Raw(data) => image_binary_to_pixbuf(data),
-                Image::File(path) => match std::fs::read(path) {
-                    Ok(bytes) => image_binary_to_pixbuf(&bytes),
-                    Err(_) => None,
-                },
-            };
-            if let Some(icon) = icon_pixbuf {
-                drag_context.drag_set_icon_pixbuf(&icon, 0, 0);
+            on_dnd_finished(callback.clone(), window, &drag_context);
+            on_drag_end(window, &handler_ids);
+
+            let emitter_ = emitter.clone();
+            drag_context.connect_action_changed(move |_, action| {
+                emitter_.emit(DragEvent::ActionChanged(drag_action_from_gdk(action)));
+            });
+            emitter.emit(DragEvent::Started);
+            track_cursor(window, emitter.clone());
+
+            if let Some(icon) = pixbuf.and_then(|pixbuf| {
+                drag_icon_surface(
+                    &pixbuf,
+                    hotspot,
+                    scale_factor,
+                    max_size,
+                    opacity,
+                    window.scale_factor(),
+                )
+            }) {
+                drag_context.drag_set_icon_surface(&icon);
             }
 
-            Ok(())
+            Ok(emitter.session(Some(Box::new(move || drag_context.drag_cancel()))))
         } else {
+            cleanup_signal_handlers(&handler_ids, window);
             Err(crate::Error::FailedToStartDrag)
         }
     } else {
+        cleanup_signal_handlers(&handler_ids, window);
         Err(crate::Error::EmptyTargetList)
     }
 }
 
-fn image_binary_to_pixbuf(data: &[u8]) -> Option<gdk_pixbuf::Pixbuf> {
+fn set_files_data(
+    data: &gtk::SelectionData,
+    info: u32,
+    paths: &[PathBuf],
+    separator: FileTextSeparator,
+) {
+    if info == INFO_URI {
+        let uris: Vec<String> = paths
+            .iter()
+            .filter_map(|path| crate::uri::from_path(path, None).ok())
+            .collect();
+        let uris: Vec<&str> = uris.iter().map(|s| s.as_str()).collect();
+        data.set_uris(&uris);
+    } else {
+        data.set_text(&crate::path::shell_quoted_list(paths, separator));
+    }
+}
+
+/// Saves the file to the URI the drop target stored in the XDS property of the source window.
+///
+/// Returns the XDS status: `S` on success, `F` to ask for the `application/octet-stream` target instead
+/// when the location is on another host, and `E` on error.
+fn direct_save(context: &gdk::DragContext, store: &VirtualFileStore) -> &'static [u8] {
+    let Some((_, _, uri)) = gdk::property_get(
+        &context.source_window(),
+        &gdk::Atom::intern(XDS_ATOM),
+        &gdk::ATOM_NONE,
+        0,
+        4096,
+        0,
+    ) else {
+        return b"E";
+    };
+
+    let uri = String::from_utf8_lossy(&uri);
+    let path = match crate::uri::to_path(uri.trim_end_matches('\0')) {
+        Ok((path, None)) => path,
+        Ok((path, Some(host))) if host == glib::host_name() => path,
+        Ok(_) => return b"F",
+        Err(_) => return b"E",
+    };
+
+    match store.save_to(0, &path) {
+        Ok(()) => b"S",
+        Err(_) => b"E",
+    }
+}
+
+/// Loads the previews, stacking them when there are several.
+fn drag_pixbuf(image: Image, strict: bool) -> crate::Result<Option<gdk_pixbuf::Pixbuf>> {
+    let previews = image.into_previews();
+    let count = previews.len();
+    let mut pixbufs = crate::image::load_previews(
+        previews.into_iter().take(MAX_STACKED_PREVIEWS),
+        strict,
+        |preview| image_to_pixbuf(&preview),
+    )?;
+    if count == 1 {
+        Ok(pixbufs.pop())
+    } else {
+        Ok(stack_pixbufs(&pixbufs, count))
+    }
+}
+
+#[cfg(not(feature = "image-decode"))]
+pub(crate) fn validate_image(image: &Image) -> crate::Result<()> {
+    image_to_pixbuf(image).map(drop)
+}
+
+fn image_to_pixbuf(image: &Image) -> crate::Result<gdk_pixbuf::Pixbuf> {
+    match image {
+        Image::Raw(data) => {
+            image_binary_to_pixbuf(data).map_err(|e| crate::image::decode_error(None, e))
+        }
+        Image::File(path) => image_binary_to_pixbuf(&crate::image::read_file(path)?)
+            .map_err(|e| crate::image::decode_error(Some(path), e)),
+        Image::Rgba {
+            width,
+            height,
+            stride,
+            pixels,
+        } => {
+            crate::image::check_rgba(*width, *height, *stride, pixels)?;
+            Ok(rgba_to_pixbuf(
+                glib::Bytes::from(pixels.as_slice()),
+                *width,
+                *height,
+                *stride,
+            ))
+        }
+        Image::Prepared(prepared) => Ok(rgba_to_pixbuf(
+            // shares the pixels instead of copying them
+            glib::Bytes::from_owned(prepared.pixels().clone()),
+            prepared.width(),
+            prepared.height(),
+            prepared.stride(),
+        )),
+        // flattened by `into_previews`, the first image is used otherwise
+        Image::Stack(images) => match images.first() {
+            Some(image) => image_to_pixbuf(image),
+            None => Err(crate::image::decode_error(None, "empty image stack")),
+        },
+        // replaced by the previews of the files, unless nested in a stack
+        Image::Auto => Err(crate::image::decode_error(
+            None,
+            "automatic previews can not be stacked",
+        )),
+    }
+}
+
+fn rgba_to_pixbuf(pixels: glib::Bytes, width: u32, height: u32, stride: u32) -> gdk_pixbuf::Pixbuf {
+    gdk_pixbuf::Pixbuf::from_bytes(
+        &pixels,
+        gdk_pixbuf::Colorspace::Rgb,
+        true,
+        8,
+        width as i32,
+        height as i32,
+        stride as i32,
+    )
+}
+
+#[cfg(not(feature = "image-decode"))]
+pub(crate) fn prepare_image(image: &Image) -> crate::Result<crate::PreparedImage> {
+    pixbuf_to_prepared(image_to_pixbuf(image)?)
+}
+
+/// Loads a thumbnail of an image file, or the icon of the content type of other files from the icon theme.
+pub(crate) fn auto_preview(path: &Path, size: u32) -> crate::Result<crate::PreparedImage> {
+    let info = gio::File::for_path(path)
+        .query_info(
+            "standard::content-type,standard::icon",
+            gio::FileQueryInfoFlags::NONE,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|e| crate::image::decode_error(Some(path), e))?;
+    let is_image = info
+        .content_type()
+        .and_then(|content_type| gio::content_type_get_mime_type(&content_type))
+        .is_some_and(|mime_type| mime_type.starts_with("image/"));
+
+    // gdk-pixbuf can't decode every image type, those get their icon
+    let thumbnail = is_image
+        .then(|| gdk_pixbuf::Pixbuf::from_file_at_scale(path, size as i32, size as i32, true).ok())
+        .flatten();
+    let pixbuf = match thumbnail {
+        Some(thumbnail) => thumbnail,
+        None => info
+            .icon()
+            .and_then(|icon| {
+                gtk::IconTheme::default()?.lookup_by_gicon(
+                    &icon,
+                    size as i32,
+                    gtk::IconLookupFlags::FORCE_SIZE,
+                )
+            })
+            .ok_or_else(|| {
+                crate::image::decode_error(Some(path), "no icon found for the content type")
+            })?
+            .load_icon()
+            .map_err(|e| crate::image::decode_error(Some(path), e))?,
+    };
+    pixbuf_to_prepared(pixbuf)
+}
+
+fn pixbuf_to_prepared(pixbuf: gdk_pixbuf::Pixbuf) -> crate::Result<crate::PreparedImage> {
+    let pixbuf = if pixbuf.has_alpha() {
+        pixbuf
+    } else {
+        pixbuf
+            .add_alpha(false, 0, 0, 0)
+            .ok_or_else(|| crate::image::decode_error(None, "failed to add an alpha channel"))?
+    };
+    let pixels = pixbuf
+        .read_pixel_bytes()
+        .ok_or_else(|| crate::image::decode_error(None, "failed to read the pixels"))?;
+    Ok(crate::PreparedImage::from_rgba(
+        pixbuf.width() as u32,
+        pixbuf.height() as u32,
+        pixbuf.rowstride() as u32,
+        &pixels,
+    ))
+}
+
+/// Stacks the previews diagonally with the first one on top,
+/// adding a badge with the number of items when there are more than the stack shows.
+fn stack_pixbufs(pixbufs: &[gdk_pixbuf::Pixbuf], count: usize) -> Option<gdk_pixbuf::Pixbuf> {
+    let shift = STACK_OFFSET * (pixbufs.len() as i32 - 1);
+    let badge_radius = if count > pixbufs.len() {
+        BADGE_RADIUS
+    } else {
+        0.
+    };
+    // the badge overlaps the top right corner of the first preview
+    let margin = badge_radius.ceil() as i32;
+    let width = pixbufs.iter().map(|pixbuf| pixbuf.width()).max()? + shift + margin;
+    let height = pixbufs.iter().map(|pixbuf| pixbuf.height()).max()? + shift + margin;
+
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height).ok()?;
+    let cr = cairo::Context::new(&surface).ok()?;
+    for (index, pixbuf) in pixbufs.iter().enumerate().rev() {
+        let offset = (STACK_OFFSET * index as i32) as f64;
+        cr.set_source_pixbuf(pixbuf, offset, margin as f64 + offset);
+        cr.paint().ok()?;
+    }
+
+    if badge_radius > 0. {
+        let center_x = (pixbufs[0].width() + margin) as f64 - badge_radius;
+        let center_y = badge_radius;
+        cr.arc(
+            center_x,
+            center_y,
+            badge_radius,
+            0.,
+            2. * std::f64::consts::PI,
+        );
+        cr.set_source_rgb(0.86, 0.2, 0.18);
+        cr.fill().ok()?;
+
+        let label = count.to_string();
+        cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+        cr.set_font_size(badge_radius);
+        let extents = cr.text_extents(&label).ok()?;
+        cr.move_to(
+            center_x - extents.width / 2. - extents.x_bearing,
+            center_y - extents.height / 2. - extents.y_bearing,
+        );
+        cr.set_source_rgb(1., 1., 1.);
+        cr.show_text(&label).ok()?;
+    }
+    drop(cr);
+
+    gdk::pixbuf_get_from_surface(&surface, 0, 0, width, height)
+}
+
+/// Draws the drag image at its logical size on a surface matching the scale of the window.
+fn drag_icon_surface(
+    pixbuf: &gdk_pixbuf::Pixbuf,
+    hotspot: Option<(i32, i32)>,
+    scale_factor: f64,
+    max_size: Option<(u32, u32)>,
+    opacity: f64,
+    window_scale: i32,
+) -> Option<cairo::ImageSurface> {
+    let (width, height) = crate::image::logical_size(
+        pixbuf.width() as u32,
+        pixbuf.height() as u32,
+        scale_factor,
+        max_size,
+    );
+    let window_scale = window_scale.max(1) as f64;
+    let surface = cairo::ImageSurface::create(
+        cairo::Format::ARgb32,
+        ((width * window_scale).ceil() as i32).max(1),
+        ((height * window_scale).ceil() as i32).max(1),
+    )
+    .ok()?;
+    surface.set_device_scale(window_scale, window_scale);
+
+    let cr = cairo::Context::new(&surface).ok()?;
+    cr.scale(
+        width / pixbuf.width() as f64,
+        height / pixbuf.height() as f64,
+    );
+    cr.set_source_pixbuf(pixbuf, 0., 0.);
+    cr.paint_with_alpha(opacity.clamp(0., 1.)).ok()?;
+    drop(cr);
+
+    // GTK places the point at the negated device offset under the cursor
+    let (hotspot_x, hotspot_y) = hotspot.unwrap_or_default();
+    surface.set_device_offset(
+        -hotspot_x as f64 * window_scale,
+        -hotspot_y as f64 * window_scale,
+    );
+    Some(surface)
+}
+
+fn image_binary_to_pixbuf(data: &[u8]) -> Result<gdk_pixbuf::Pixbuf, String> {
     let loader = gdk_pixbuf::PixbufLoader::new();
     loader
         .write(data)
         .and_then(|_| loader.close())
-        .map_err(|_| ())
-        .and_then(|_| loader.pixbuf().ok_or(()))
-        .ok()
+        .map_err(|e| e.to_string())?;
+    loader
+        .pixbuf()
+        .ok_or_else(|| "no image was decoded".to_string())
 }
 
 fn clear_signal_handlers(window: &gtk::ApplicationWindow, handler_ids: &mut Vec<SignalHandlerId>) {
@@ -99,27 +666,31 @@ fn clear_signal_handlers(window: &gtk::ApplicationWindow, handler_ids: &mut Vec<
     }
 }
 
-fn on_drop_failed<F: Fn(DragResult, CursorPosition) + Send + 'static>(
+fn on_drop_failed<F: Fn(DragResult, CursorPosition) + 'static>(
     callback: Rc<F>,
     window: &gtk::ApplicationWindow,
     handler_ids: &Arc<Mutex<Vec<SignalHandlerId>>>,
     options: &Options,
 ) {
     let window_clone = window.clone();
-    let handler_ids_clone = handler_ids.clone();
 
     let skip_animatation_on_cancel_or_failure = options.skip_animatation_on_cancel_or_failure;
 
     handler_ids
         .lock()
         .unwrap()
-        .push(window.connect_drag_failed(move |_, _, _drag_result| {
+        .push(window.connect_drag_failed(move |_, _, drag_result| {
+            let reason = match drag_result {
+                gtk::DragResult::NoTarget => CancelReason::NoTarget,
+                gtk::DragResult::UserCancelled => CancelReason::UserCancelled,
+                gtk::DragResult::TimeoutExpired => CancelReason::TimeoutExpired,
+                gtk::DragResult::GrabBroken => CancelReason::GrabBroken,
+                _ => CancelReason::Error,
+            };
             callback(
-                DragResult::Cancel,
+                DragResult::Cancel(reason),
                 get_cursor_position(&window_clone).unwrap(),
             );
-
-            cleanup_signal_handlers(&handler_ids_clone, &window_clone);
             Inhibit(skip_animatation_on_cancel_or_failure)
         }));
 }
@@ -133,18 +704,78 @@ fn cleanup_signal_handlers(
     window.drag_source_unset();
 }
 
-fn on_drop_performed<F: Fn(DragResult, CursorPosition) + Send + 'static>(
+/// Disconnects the handlers once the drag operation ended.
+///
+/// `drag-end` is emitted after `drag-failed` or once the drop target finished reading the data.
+fn on_drag_end(window: &gtk::ApplicationWindow, handler_ids: &Arc<Mutex<Vec<SignalHandlerId>>>) {
+    let handler_ids_clone = handler_ids.clone();
+    handler_ids
+        .lock()
+        .unwrap()
+        .push(window.connect_drag_end(move |window, _| {
+            cleanup_signal_handlers(&handler_ids_clone, window);
+        }));
+}
+
+/// Reports the drop once the drop target finished reading the data.
+///
+/// `drop-performed` is emitted before the drop target even asked for the data,
+/// so it can't tell whether the drop target accepted the drop.
+fn on_dnd_finished<F: Fn(DragResult, CursorPosition) + 'static>(
     callback: Rc<F>,
     window: &gtk::ApplicationWindow,
-    handler_ids: &Arc<Mutex<Vec<SignalHandlerId>>>,
     drag_context: &gdk::DragContext,
 ) {
     let window = window.clone();
-    let handler_ids = handler_ids.clone();
 
-    drag_context.connect_drop_performed(move |_, _| {
-        cleanup_signal_handlers(&handler_ids, &window);
-        callback(DragResult::Dropped, get_cursor_position(&window).unwrap());
+    drag_context.connect_dnd_finished(move |context| {
+        // the selected action is kept on X11 when the drop target refused the drop
+        let result = if context.drag_drop_succeeded() {
+            DragResult::Dropped(
+                drag_action_from_gdk(context.selected_action()).unwrap_or(DragAction::Copy),
+            )
+        } else {
+            DragResult::Cancel(CancelReason::NoTarget)
+        };
+        callback(result, get_cursor_position(&window).unwrap());
+    });
+}
+
+/// Polls the cursor while the drag operation is active since the source window doesn't receive motion events.
+fn track_cursor(window: &gtk::ApplicationWindow, emitter: Rc<DragEmitter>) {
+    let window = window.clone();
+    let mut last_position = None;
+    let mut inside_window = true;
+
+    glib::timeout_add_local(Duration::from_millis(16), move || {
+        if !emitter.is_active() {
+            return glib::Continue(false);
+        }
+
+        if let Ok(position) = get_cursor_position(&window) {
+            if last_position != Some(position) {
+                last_position.replace(position);
+                emitter.emit(DragEvent::Moved(position));
+            }
+
+            let is_inside_window = window.window().is_some_and(|gdk_window| {
+                let frame = gdk_window.frame_extents();
+                position.x >= frame.x()
+                    && position.x < frame.x() + frame.width()
+                    && position.y >= frame.y()
+                    && position.y < frame.y() + frame.height()
+            });
+            if is_inside_window != inside_window {
+                inside_window = is_inside_window;
+                emitter.emit(if is_inside_window {
+                    DragEvent::ReenteredSourceWindow
+                } else {
+                    DragEvent::LeftSourceWindow
+                });
+            }
+        }
+
+        glib::Continue(true)
     });
 }
 
@@ -160,3 +791,23 @@ fn get_cursor_position(window: &gtk::ApplicationWindow) -> Result<CursorPosition
         Err(Error::FailedToGetCursorPosition)
     }
 }
+
+fn drag_action_to_gdk(action: DragAction) -> gdk::DragAction {
+    match action {
+        DragAction::Copy => gdk::DragAction::COPY,
+        DragAction::Move => gdk::DragAction::MOVE,
+        DragAction::Link => gdk::DragAction::LINK,
+    }
+}
+
+fn drag_action_from_gdk(action: gdk::DragAction) -> Option<DragAction> {
+    if action.contains(gdk::DragAction::MOVE) {
+        Some(DragAction::Move)
+    } else if action.contains(gdk::DragAction::LINK) {
+        Some(DragAction::Link)
+    } else if action.is_empty() {
+        None
+    } else {
+        Some(DragAction::Copy)
+    }
+}
@@ -2,12 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use std::ffi::{c_char, c_void};
+use std::{
+    cell::Cell,
+    ffi::{c_char, c_void},
+    path::Path,
+    sync::Arc,
+};
 
 use cocoa::{
-    appkit::{NSAlignmentOptions, NSApp, NSEvent, NSEventModifierFlags, NSEventType, NSImage},
+    appkit::{
+        NSAlignmentOptions, NSApp, NSEvent, NSEventModifierFlags, NSEventType, NSImage,
+        NSPasteboardTypeHTML, NSPasteboardTypeString,
+    },
     base::{id, nil},
-    foundation::{NSArray, NSData, NSPoint, NSRect, NSSize, NSUInteger},
+    foundation::{NSArray, NSData, NSInteger, NSPoint, NSRect, NSSize, NSUInteger},
 };
 use core_graphics::display::CGDisplay;
 use objc::{
@@ -16,10 +24,22 @@ use objc::{
 };
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 
-use crate::{CursorPosition, DragItem, DragResult, Image, Options};
+use crate::{
+    session::DragEmitter, virtual_files::VirtualFileStore, CancelReason, Composite, CursorPosition,
+    DataProvider, DragAction, DragEvent, DragImage, DragItem, DragResult, DragSession, Image,
+    Options, Representation,
+};
 
 const UTF8_ENCODING: usize = 4;
 
+const NS_DRAG_OPERATION_NONE: NSUInteger = 0;
+const NS_DRAG_OPERATION_COPY: NSUInteger = 1;
+const NS_DRAG_OPERATION_LINK: NSUInteger = 2;
+const NS_DRAG_OPERATION_MOVE: NSUInteger = 16;
+
+const NS_ALPHA_NONPREMULTIPLIED_BITMAP_FORMAT: NSUInteger = 1 << 1;
+const NS_COMPOSITING_OPERATION_SOURCE_OVER: NSUInteger = 2;
+
 struct NSString(id);
 
 impl NSString {
@@ -53,10 +73,18 @@ impl NSString {
 pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Send + 'static>(
     handle: &W,
     item: DragItem,
-    image: Image,
+    image: impl Into<DragImage>,
     on_drop_callback: F,
-    options: Options,
-) -> crate::Result<()> {
+    mut options: Options,
+) -> crate::Result<DragSession> {
+    let item = crate::path::normalize_item(item, &options)?;
+    let DragImage {
+        image,
+        hotspot,
+        scale_factor,
+        max_size,
+        opacity,
+    } = crate::image::resolve(image.into(), &item, options.strict_image)?;
     if let RawWindowHandle::AppKit(w) = handle.raw_window_handle() {
         unsafe {
             let window = w.ns_window as id;
@@ -66,131 +94,171 @@ pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Sen
             let mouse_location: NSPoint = msg_send![window, mouseLocationOutsideOfEventStream];
             let current_position: NSPoint = msg_send![ns_view, backingAlignedRect: NSRect::new(mouse_location, NSSize::new(0., 0.)) options: NSAlignmentOptions::NSAlignAllEdgesOutward];
 
-            let img: id = msg_send![class!(NSImage), alloc];
-            let img: id = match image {
-                Image::File(path) => {
-                    if !path.exists() {
-                        return Err(crate::Error::ImageNotFound);
-                    }
-                    NSImage::initByReferencingFile_(img, NSString::new(&path.to_string_lossy()).0)
-                }
-                Image::Raw(bytes) => {
-                    let data = NSData::dataWithBytes_length_(
-                        nil,
-                        bytes.as_ptr() as *const std::os::raw::c_void,
-                        bytes.len() as u64,
+            let previews = crate::image::load_previews(
+                image.into_previews(),
+                options.strict_image,
+                |image| {
+                    let img = ns_image(&image)?;
+                    let image_size: NSSize = img.size();
+                    let (width, height) = crate::image::logical_size(
+                        image_size.width.round() as u32,
+                        image_size.height.round() as u32,
+                        scale_factor,
+                        max_size,
                     );
-                    NSImage::initWithData_(NSImage::alloc(nil), data)
-                }
-            };
-            let image_size: NSSize = img.size();
-            let image_rect = NSRect::new(
-                NSPoint::new(
-                    current_position.x - image_size.width / 2.,
-                    current_position.y - image_size.height / 2.,
-                ),
-                image_size,
-            );
+                    let size = NSSize::new(width, height);
+                    if opacity < 1. {
+                        Ok((translucent_ns_image(img, size, opacity), size))
+                    } else {
+                        Ok((img, size))
+                    }
+                },
+            )?;
 
             let dragging_items: id = msg_send![class!(NSMutableArray), array];
 
+            let mut virtual_files = None;
             match item {
                 DragItem::Files(files) => {
-                    for path in files {
-                        let nsurl: id = msg_send![class!(NSURL), fileURLWithPath: NSString::new(&path.display().to_string()) isDirectory: false];
-                        let drag_item: id = msg_send![class!(NSDraggingItem), alloc];
-                        let item: id = msg_send![drag_item, initWithPasteboardWriter: nsurl];
-
-                        let _: () = msg_send![item, setDraggingFrame: image_rect contents: img];
-
+                    let (paths, links) = crate::path::display_paths(files)?;
+                    virtual_files = links;
+                    for path in paths {
+                        let item = string_dragging_item(&[
+                            (
+                                NSString::new("public.file-url").0,
+                                &crate::uri::from_path(&path, None)?,
+                            ),
+                            (
+                                NSPasteboardTypeString,
+                                &crate::path::shell_quote(&path.to_string_lossy()),
+                            ),
+                        ]);
                         let _: () = msg_send![dragging_items, addObject: item];
                     }
                 }
-                DragItem::Data { provider, types } => {
-                    let cls = ClassDecl::new("DragRsDataProvider", class!(NSObject));
-                    let cls = match cls {
-                        Some(mut cls) => {
-                            cls.add_ivar::<*mut c_void>("provider_ptr");
-                            cls.add_protocol(
-                                Protocol::get("NSPasteboardItemDataProvider").unwrap(),
-                            );
-                            cls.add_method(
-                                sel!(pasteboard:item:provideDataForType:),
-                                provide_data as extern "C" fn(&Object, Sel, id, id, id),
-                            );
-                            cls.add_method(
-                                sel!(pasteboardFinishedWithDataProvider:),
-                                pasteboard_finished as extern "C" fn(&Object, Sel, id),
-                            );
-
-                            extern "C" fn pasteboard_finished(
-                                this: &Object,
-                                _: Sel,
-                                _pasteboard: id,
-                            ) {
-                                unsafe {
-                                    let provider = this.get_ivar::<*mut c_void>("provider_ptr");
-                                    drop(Box::from_raw(
-                                        *provider as *mut Box<dyn Fn(&str) -> Option<Vec<u8>>>,
-                                    ));
-                                }
-                            }
+                DragItem::Text(text) => {
+                    let item = string_dragging_item(&[(NSPasteboardTypeString, &text)]);
+                    let _: () = msg_send![dragging_items, addObject: item];
+                }
+                DragItem::Uris(uris) => {
+                    for uri in &uris {
+                        let item = string_dragging_item(&[
+                            (NSString::new("public.url").0, &uri.uri),
+                            (NSString::new("public.url-name").0, uri.title_or_uri()),
+                            (NSPasteboardTypeString, &uri.uri),
+                        ]);
+                        let _: () = msg_send![dragging_items, addObject: item];
+                    }
+                }
+                DragItem::Html { html, text } => {
+                    let text = text.unwrap_or_else(|| crate::html::to_plain_text(&html));
+                    let item = string_dragging_item(&[
+                        (NSPasteboardTypeHTML, &html),
+                        (NSPasteboardTypeString, &text),
+                    ]);
+                    let _: () = msg_send![dragging_items, addObject: item];
+                }
+                DragItem::VirtualFiles(files) => {
+                    let store = VirtualFileStore::new(files);
+                    add_virtual_file_dragging_items(dragging_items, &store);
+                    virtual_files.replace(store);
+                }
+                // XDS is specific to X11, so the file is always written to the temporary directory
+                DragItem::DirectSave(file) => {
+                    let store = VirtualFileStore::new(vec![file]);
+                    add_virtual_file_dragging_items(dragging_items, &store);
+                    virtual_files.replace(store);
+                }
+                DragItem::Composite(Composite {
+                    files,
+                    text,
+                    html,
+                    types,
+                    provider,
+                }) => {
+                    let (paths, links) = crate::path::display_paths(files)?;
+                    virtual_files = links;
+                    let text = text.or_else(|| html.as_deref().map(crate::html::to_plain_text));
+                    let file_url = paths
+                        .first()
+                        .map(|path| crate::uri::from_path(path, None))
+                        .transpose()?;
+
+                    let mut pasteboard_types = types.clone();
+                    if file_url.is_some() {
+                        pasteboard_types.push("public.file-url".into());
+                    }
+                    if html.is_some() {
+                        pasteboard_types.push("public.html".into());
+                    }
+                    if text.is_some() {
+                        pasteboard_types.push("public.utf8-plain-text".into());
+                    }
 
-                            extern "C" fn provide_data(
-                                this: &Object,
-                                _: Sel,
-                                _pasteboard: id,
-                                item: id,
-                                data_type: id,
-                            ) {
-                                unsafe {
-                                    let provider = this.get_ivar::<*mut c_void>("provider_ptr");
-
-                                    let provider =
-                                        &*(*provider as *mut Box<dyn Fn(&str) -> Option<Vec<u8>>>);
-
-                                    if let Some(data) = provider(NSString(data_type).to_str()) {
-                                        let bytes = data.as_ptr() as *mut c_void;
-                                        let length = data.len();
-                                        let data: id = msg_send![class!(NSData), alloc];
-                                        let data: id = msg_send![data, initWithBytesNoCopy:bytes length:length freeWhenDone: if length == 0 { NO } else { YES }];
-
-                                        let _: () =
-                                            msg_send![item, setData: data forType: data_type];
-                                    }
-                                }
+                    let item = data_provider_dragging_item(
+                        Box::new(move |data_type| {
+                            if types.iter().any(|t| t == data_type) {
+                                return provider(Representation::Data(data_type));
                             }
+                            let (representation, data) = match data_type {
+                                "public.file-url" => (Representation::Files, file_url.as_ref()?),
+                                "public.html" => (Representation::Html, html.as_ref()?),
+                                _ => (Representation::Text, text.as_ref()?),
+                            };
+                            provider(representation);
+                            Some(data.as_bytes().to_vec())
+                        }),
+                        &pasteboard_types,
+                    );
+                    let _: () = msg_send![dragging_items, addObject: item];
 
-                            cls.register()
-                        }
-                        None => Class::get("DragRsDataProvider")
-                            .expect("Failed to get the class definition"),
-                    };
-
-                    let data_provider: id = msg_send![cls, alloc];
-                    let data_provider: id = msg_send![data_provider, init];
-
-                    let provider_ptr = Box::into_raw(Box::new(provider));
-                    (*data_provider)
-                        .set_ivar("provider_ptr", provider_ptr as *mut _ as *mut c_void);
-
-                    let item: id = msg_send![class!(NSPasteboardItem), alloc];
-                    let item: id = msg_send![item, init];
-                    let types = types
-                        .into_iter()
-                        .map(|t| NSString::new(&t).0)
-                        .collect::<Vec<id>>();
-                    let _: () = msg_send![item, setDataProvider: data_provider forTypes: NSArray::arrayWithObjects(nil, &types)];
-
-                    let drag_item: id = msg_send![class!(NSDraggingItem), alloc];
-                    let item: id = msg_send![drag_item, initWithPasteboardWriter: item];
-
-                    let _: () = msg_send![item, setDraggingFrame: image_rect contents: img];
-
+                    // a pasteboard item holds a single file URL
+                    for path in paths.iter().skip(1) {
+                        let item = string_dragging_item(&[(
+                            NSString::new("public.file-url").0,
+                            &crate::uri::from_path(path, None)?,
+                        )]);
+                        let _: () = msg_send![dragging_items, addObject: item];
+                    }
+                }
+                DragItem::Data { provider, types } => {
+                    let item = data_provider_dragging_item(provider, &types);
                     let _: () = msg_send![dragging_items, addObject: item];
                 }
             }
 
+            // the dragging frames are in the coordinate system of the view
+            let view_position: NSPoint =
+                msg_send![ns_view, convertPoint: mouse_location fromView: nil];
+            let is_flipped: BOOL = msg_send![ns_view, isFlipped];
+            let is_flipped = is_flipped == YES;
+
+            // each item gets its own preview, the last one is used for the remaining items
+            let count: NSUInteger = msg_send![dragging_items, count];
+            for index in 0..count {
+                let item: id = msg_send![dragging_items, objectAtIndex: index];
+                let (img, image_size) = match previews.get(index as usize).or(previews.last()) {
+                    Some(preview) => *preview,
+                    None => (msg_send![NSImage::alloc(nil), init], NSSize::new(0., 0.)),
+                };
+                // the hotspot is relative to the top-left corner, which is the frame origin only in flipped views
+                let origin = match hotspot {
+                    Some((x, y)) if is_flipped => {
+                        NSPoint::new(view_position.x - x as f64, view_position.y - y as f64)
+                    }
+                    Some((x, y)) => NSPoint::new(
+                        view_position.x - x as f64,
+                        view_position.y - image_size.height + y as f64,
+                    ),
+                    None => NSPoint::new(
+                        view_position.x - image_size.width / 2.,
+                        view_position.y - image_size.height / 2.,
+                    ),
+                };
+                let image_rect = NSRect::new(origin, image_size);
+                let _: () = msg_send![item, setDraggingFrame: image_rect contents: img];
+            }
+
             let drag_event: id = msg_send![class!(NSEvent), alloc];
             let current_event: id = msg_send![NSApp(), currentEvent];
             let drag_event: id = NSEvent::mouseEventWithType_location_modifierFlags_timestamp_windowNumber_context_eventNumber_clickCount_pressure_(
@@ -211,6 +279,12 @@ pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Sen
                 Some(mut cls) => {
                     cls.add_ivar::<*mut c_void>("on_drop_ptr");
                     cls.add_ivar::<BOOL>("animate_on_cancel_or_failure");
+                    cls.add_ivar::<NSUInteger>("operation_mask");
+                    cls.add_ivar::<*mut c_void>("tracker_ptr");
+                    cls.add_method(
+                        sel!(draggingSession:movedToPoint:),
+                        dragging_session_moved as extern "C" fn(&Object, Sel, id, NSPoint),
+                    );
                     cls.add_method(
                         sel!(draggingSession:sourceOperationMaskForDraggingContext:),
                         dragging_session
@@ -226,22 +300,25 @@ pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Sen
                         this: &Object,
                         _: Sel,
                         dragging_session: id,
-                        context: NSUInteger,
+                        _context: NSUInteger,
                     ) -> NSUInteger {
                         unsafe {
                             let animates = this.get_ivar::<BOOL>("animate_on_cancel_or_failure");
                             let () = msg_send![dragging_session, setAnimatesToStartingPositionsOnCancelOrFail: *animates];
-                        }
 
-                        if context == 0 {
-                            // NSDragOperationCopy
-                            1
-                        } else {
-                            // NSDragOperationEvery
-                            NSUInteger::max_value()
+                            *this.get_ivar::<NSUInteger>("operation_mask")
                         }
                     }
 
+                    extern "C" fn dragging_session_moved(
+                        this: &Object,
+                        _: Sel,
+                        _dragging_session: id,
+                        screen_point: NSPoint,
+                    ) {
+                        unsafe { tracker(this).update_position(screen_point) };
+                    }
+
                     extern "C" fn dragging_session_end(
                         this: &Object,
                         _: Sel,
@@ -252,22 +329,23 @@ pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Sen
                         unsafe {
                             let callback = this.get_ivar::<*mut c_void>("on_drop_ptr");
 
-                            let mouse_location = CursorPosition {
-                                x: ended_at_point.x as i32,
-                                y: CGDisplay::main().pixels_high() as i32 - ended_at_point.y as i32,
-                            };
+                            let mouse_location = cursor_position(ended_at_point);
 
                             let callback_closure =
                                 &*(*callback as *mut Box<dyn Fn(DragResult, CursorPosition)>);
 
-                            if operation == 0 {
-                                // NSDragOperationNone
-                                callback_closure(DragResult::Cancel, mouse_location);
+                            let result = if operation == NS_DRAG_OPERATION_NONE {
+                                DragResult::Cancel(CancelReason::NoTarget)
                             } else {
-                                callback_closure(DragResult::Dropped, mouse_location);
-                            }
+                                DragResult::Dropped(drag_action_from_operation(operation))
+                            };
+                            tracker(this).emit(DragEvent::Finished(result));
+                            callback_closure(result, mouse_location);
 
                             drop(Box::from_raw(*callback as *mut Box<dyn Fn(DragResult)>));
+                            drop(Box::from_raw(
+                                *this.get_ivar::<*mut c_void>("tracker_ptr") as *mut DragTracker
+                            ));
                         }
                     }
 
@@ -287,12 +365,468 @@ pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Sen
                 "animate_on_cancel_or_failure",
                 !options.skip_animatation_on_cancel_or_failure,
             );
+            let emitter = DragEmitter::new(options.on_event.take());
+            if let Some(store) = virtual_files {
+                let policy = options.virtual_files_cleanup;
+                emitter.on_finished(Box::new(move || store.cleanup(policy)));
+            }
+            let session = emitter.session(None);
+            let tracker_ptr = Box::into_raw(Box::new(DragTracker {
+                window,
+                emitter,
+                inside_window: Cell::new(true),
+            }));
+            (*source).set_ivar("tracker_ptr", tracker_ptr as *mut c_void);
+            (*source).set_ivar(
+                "operation_mask",
+                options
+                    .allowed_actions()
+                    .iter()
+                    .fold(NS_DRAG_OPERATION_NONE, |mask, action| {
+                        mask | operation_from_drag_action(*action)
+                    }),
+            );
 
             let _: () = msg_send![ns_view, beginDraggingSessionWithItems: dragging_items event: drag_event source: source];
-        }
+            (*tracker_ptr).emit(DragEvent::Started);
 
-        Ok(())
+            Ok(session)
+        }
     } else {
         Err(crate::Error::UnsupportedWindowHandle)
     }
 }
+
+/// Reports the progress of the drag operation from the dragging source callbacks.
+struct DragTracker {
+    window: id,
+    emitter: DragEmitter,
+    inside_window: Cell<bool>,
+}
+
+impl DragTracker {
+    fn emit(&self, event: DragEvent) {
+        self.emitter.emit(event);
+    }
+
+    unsafe fn update_position(&self, screen_point: NSPoint) {
+        self.emit(DragEvent::Moved(cursor_position(screen_point)));
+
+        let frame: NSRect = msg_send![self.window, frame];
+        let inside_window = screen_point.x >= frame.origin.x
+            && screen_point.x < frame.origin.x + frame.size.width
+            && screen_point.y >= frame.origin.y
+            && screen_point.y < frame.origin.y + frame.size.height;
+        if self.inside_window.replace(inside_window) != inside_window {
+            self.emit(if inside_window {
+                DragEvent::ReenteredSourceWindow
+            } else {
+                DragEvent::LeftSourceWindow
+            });
+        }
+    }
+}
+
+/// Runs the task on the main thread, where AppKit windows live.
+#[cfg(feature = "async")]
+pub fn run_on_main_thread(
+    handle: &RawWindowHandle,
+    task: Box<dyn FnOnce() + Send>,
+) -> crate::Result<()> {
+    #[link(name = "System", kind = "dylib")]
+    extern "C" {
+        static _dispatch_main_q: c_void;
+        fn dispatch_async_f(
+            queue: *const c_void,
+            context: *mut c_void,
+            work: extern "C" fn(*mut c_void),
+        );
+    }
+
+    extern "C" fn run_task(context: *mut c_void) {
+        let task = unsafe { Box::from_raw(context as *mut Box<dyn FnOnce() + Send>) };
+        task();
+    }
+
+    if !matches!(handle, RawWindowHandle::AppKit(_)) {
+        return Err(crate::Error::UnsupportedWindowHandle);
+    }
+
+    unsafe {
+        let is_main_thread: BOOL = msg_send![class!(NSThread), isMainThread];
+        if is_main_thread == YES {
+            task();
+        } else {
+            dispatch_async_f(
+                &_dispatch_main_q,
+                Box::into_raw(Box::new(task)) as *mut c_void,
+                run_task,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "image-decode"))]
+pub(crate) fn validate_image(image: &Image) -> crate::Result<()> {
+    unsafe {
+        let img = ns_image(image)?;
+        let _: () = msg_send![img, release];
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "image-decode"))]
+pub(crate) fn prepare_image(image: &Image) -> crate::Result<crate::PreparedImage> {
+    use cocoa::foundation::NSAutoreleasePool;
+
+    unsafe {
+        // the image might be prepared on a thread without an autorelease pool
+        let pool = NSAutoreleasePool::new(nil);
+        let prepared = ns_image(image).and_then(|img| {
+            let (width, height) = ns_image_pixel_size(img);
+            let prepared = ns_image_pixels(img, width, height);
+            let _: () = msg_send![img, release];
+            prepared
+        });
+        pool.drain();
+        prepared
+    }
+}
+
+/// Draws a thumbnail of an image file, or the icon NSWorkspace shows for other files.
+pub(crate) fn auto_preview(path: &Path, size: u32) -> crate::Result<crate::PreparedImage> {
+    unsafe {
+        let img = match ns_image(&Image::File(path.to_path_buf())) {
+            Err(crate::Error::ImageNotFound) => return Err(crate::Error::ImageNotFound),
+            Ok(img) => img,
+            Err(_) => {
+                let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+                let icon: id =
+                    msg_send![workspace, iconForFile: NSString::new(&path.to_string_lossy()).0];
+                // owned like the images of `ns_image`
+                msg_send![icon, retain]
+            }
+        };
+
+        let image_size: NSSize = img.size();
+        let prepared = if image_size.width > 0. && image_size.height > 0. {
+            let fit = (size as f64 / image_size.width).min(size as f64 / image_size.height);
+            ns_image_pixels(
+                img,
+                (image_size.width * fit).round().max(1.) as u32,
+                (image_size.height * fit).round().max(1.) as u32,
+            )
+        } else {
+            Err(crate::image::decode_error(Some(path), "empty image"))
+        };
+        let _: () = msg_send![img, release];
+        prepared
+    }
+}
+
+/// The size of the first representation of the image, or the size of the image for vector images.
+#[cfg(not(feature = "image-decode"))]
+unsafe fn ns_image_pixel_size(img: id) -> (u32, u32) {
+    let representations: id = msg_send![img, representations];
+    let representation: id = msg_send![representations, firstObject];
+    let (width, height): (NSInteger, NSInteger) = if representation == nil {
+        (0, 0)
+    } else {
+        (
+            msg_send![representation, pixelsWide],
+            msg_send![representation, pixelsHigh],
+        )
+    };
+    // vector images have no pixel size
+    if width > 0 && height > 0 {
+        (width as u32, height as u32)
+    } else {
+        let size: NSSize = img.size();
+        (size.width.ceil() as u32, size.height.ceil() as u32)
+    }
+}
+
+/// Draws the image on a bitmap of the given size.
+unsafe fn ns_image_pixels(img: id, width: u32, height: u32) -> crate::Result<crate::PreparedImage> {
+    if width == 0 || height == 0 {
+        return Err(crate::image::decode_error(None, "empty image"));
+    }
+
+    let row_len = width as usize * 4;
+    let bitmap: id = msg_send![class!(NSBitmapImageRep), alloc];
+    let bitmap: id = msg_send![bitmap,
+        initWithBitmapDataPlanes: std::ptr::null_mut::<*mut u8>()
+        pixelsWide: width as NSInteger
+        pixelsHigh: height as NSInteger
+        bitsPerSample: 8 as NSInteger
+        samplesPerPixel: 4 as NSInteger
+        hasAlpha: YES
+        isPlanar: NO
+        colorSpaceName: NSString::new("NSDeviceRGBColorSpace").0
+        // drawing needs premultiplied alpha
+        bitmapFormat: 0 as NSUInteger
+        bytesPerRow: row_len as NSInteger
+        bitsPerPixel: 32 as NSInteger];
+    let context: id =
+        msg_send![class!(NSGraphicsContext), graphicsContextWithBitmapImageRep: bitmap];
+    if context == nil {
+        let _: () = msg_send![bitmap, release];
+        return Err(crate::image::decode_error(None, "failed to draw the image"));
+    }
+
+    let _: () = msg_send![class!(NSGraphicsContext), saveGraphicsState];
+    let _: () = msg_send![class!(NSGraphicsContext), setCurrentContext: context];
+    let _: () = msg_send![img,
+        drawInRect: NSRect::new(NSPoint::new(0., 0.), NSSize::new(width as f64, height as f64))
+        // an empty rect draws the whole image
+        fromRect: NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 0.))
+        operation: NS_COMPOSITING_OPERATION_SOURCE_OVER
+        fraction: 1.0f64];
+    let _: () = msg_send![class!(NSGraphicsContext), restoreGraphicsState];
+
+    let data: *const u8 = msg_send![bitmap, bitmapData];
+    let mut pixels = std::slice::from_raw_parts(data, row_len * height as usize).to_vec();
+    let _: () = msg_send![bitmap, release];
+    crate::image::demultiply_rgba(&mut pixels);
+    Ok(crate::PreparedImage::from_rgba(
+        width,
+        height,
+        row_len as u32,
+        &pixels,
+    ))
+}
+
+unsafe fn ns_image(image: &Image) -> crate::Result<id> {
+    match image {
+        Image::File(path) => {
+            if !path.exists() {
+                return Err(crate::Error::ImageNotFound);
+            }
+            let img = NSImage::initByReferencingFile_(
+                NSImage::alloc(nil),
+                NSString::new(&path.to_string_lossy()).0,
+            );
+            // the file is only decoded when the image is drawn or validated
+            let valid: BOOL = msg_send![img, isValid];
+            if valid == YES {
+                Ok(img)
+            } else {
+                let _: () = msg_send![img, release];
+                Err(crate::image::decode_error(
+                    Some(path),
+                    "unsupported image file",
+                ))
+            }
+        }
+        Image::Raw(bytes) => {
+            let data = NSData::dataWithBytes_length_(
+                nil,
+                bytes.as_ptr() as *const std::os::raw::c_void,
+                bytes.len() as u64,
+            );
+            let img = NSImage::initWithData_(NSImage::alloc(nil), data);
+            if img == nil {
+                Err(crate::image::decode_error(None, "unsupported image data"))
+            } else {
+                Ok(img)
+            }
+        }
+        Image::Rgba {
+            width,
+            height,
+            stride,
+            pixels,
+        } => {
+            crate::image::check_rgba(*width, *height, *stride, pixels)?;
+            Ok(rgba_ns_image(*width, *height, *stride, pixels))
+        }
+        Image::Prepared(prepared) => Ok(rgba_ns_image(
+            prepared.width(),
+            prepared.height(),
+            prepared.stride(),
+            prepared.pixels(),
+        )),
+        // flattened by `into_previews`, the first image is used otherwise
+        Image::Stack(images) => match images.first() {
+            Some(image) => ns_image(image),
+            None => Err(crate::image::decode_error(None, "empty image stack")),
+        },
+        // replaced by the previews of the files, unless nested in a stack
+        Image::Auto => Err(crate::image::decode_error(
+            None,
+            "automatic previews can not be stacked",
+        )),
+    }
+}
+
+/// Draws the image at the given size and opacity on a new image, releasing the original one.
+unsafe fn translucent_ns_image(img: id, size: NSSize, opacity: f64) -> id {
+    let translucent: id = msg_send![NSImage::alloc(nil), initWithSize: size];
+    let _: () = msg_send![translucent, lockFocus];
+    let _: () = msg_send![img,
+        drawInRect: NSRect::new(NSPoint::new(0., 0.), size)
+        // an empty rect draws the whole image
+        fromRect: NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 0.))
+        operation: NS_COMPOSITING_OPERATION_SOURCE_OVER
+        fraction: opacity.max(0.)];
+    let _: () = msg_send![translucent, unlockFocus];
+    let _: () = msg_send![img, release];
+    translucent
+}
+
+/// Copies the pixels to a bitmap image representation.
+unsafe fn rgba_ns_image(width: u32, height: u32, stride: u32, pixels: &[u8]) -> id {
+    let row_len = width as usize * 4;
+    let bitmap: id = msg_send![class!(NSBitmapImageRep), alloc];
+    let bitmap: id = msg_send![bitmap,
+        initWithBitmapDataPlanes: std::ptr::null_mut::<*mut u8>()
+        pixelsWide: width as NSInteger
+        pixelsHigh: height as NSInteger
+        bitsPerSample: 8 as NSInteger
+        samplesPerPixel: 4 as NSInteger
+        hasAlpha: YES
+        isPlanar: NO
+        colorSpaceName: NSString::new("NSDeviceRGBColorSpace").0
+        bitmapFormat: NS_ALPHA_NONPREMULTIPLIED_BITMAP_FORMAT
+        bytesPerRow: row_len as NSInteger
+        bitsPerPixel: 32 as NSInteger];
+    let data: *mut u8 = msg_send![bitmap, bitmapData];
+    for (index, row) in pixels
+        .chunks(stride as usize)
+        .take(height as usize)
+        .enumerate()
+    {
+        std::ptr::copy_nonoverlapping(row.as_ptr(), data.add(index * row_len), row_len);
+    }
+
+    let img: id = msg_send![class!(NSImage), alloc];
+    let img: id = msg_send![img, initWithSize: NSSize::new(width as f64, height as f64)];
+    let _: () = msg_send![img, addRepresentation: bitmap];
+    let _: () = msg_send![bitmap, release];
+    img
+}
+
+/// Creates a dragging item whose data is generated by the provider when the drop target asks for one of the types.
+unsafe fn data_provider_dragging_item(provider: DataProvider, types: &[String]) -> id {
+    let cls = ClassDecl::new("DragRsDataProvider", class!(NSObject));
+    let cls = match cls {
+        Some(mut cls) => {
+            cls.add_ivar::<*mut c_void>("provider_ptr");
+            cls.add_protocol(Protocol::get("NSPasteboardItemDataProvider").unwrap());
+            cls.add_method(
+                sel!(pasteboard:item:provideDataForType:),
+                provide_data as extern "C" fn(&Object, Sel, id, id, id),
+            );
+            cls.add_method(
+                sel!(pasteboardFinishedWithDataProvider:),
+                pasteboard_finished as extern "C" fn(&Object, Sel, id),
+            );
+
+            extern "C" fn pasteboard_finished(this: &Object, _: Sel, _pasteboard: id) {
+                unsafe {
+                    let provider = this.get_ivar::<*mut c_void>("provider_ptr");
+                    drop(Box::from_raw(*provider as *mut DataProvider));
+                }
+            }
+
+            extern "C" fn provide_data(
+                this: &Object,
+                _: Sel,
+                _pasteboard: id,
+                item: id,
+                data_type: id,
+            ) {
+                unsafe {
+                    let provider = this.get_ivar::<*mut c_void>("provider_ptr");
+
+                    let provider = &*(*provider as *mut DataProvider);
+
+                    if let Some(data) = provider(NSString(data_type).to_str()) {
+                        // copy the bytes since the vector is freed by Rust
+                        let data: id = msg_send![class!(NSData), dataWithBytes: data.as_ptr() as *const c_void length: data.len()];
+
+                        let _: () = msg_send![item, setData: data forType: data_type];
+                    }
+                }
+            }
+
+            cls.register()
+        }
+        None => Class::get("DragRsDataProvider").expect("Failed to get the class definition"),
+    };
+
+    let data_provider: id = msg_send![cls, alloc];
+    let data_provider: id = msg_send![data_provider, init];
+
+    let provider_ptr = Box::into_raw(Box::new(provider));
+    (*data_provider).set_ivar("provider_ptr", provider_ptr as *mut _ as *mut c_void);
+
+    let item: id = msg_send![class!(NSPasteboardItem), alloc];
+    let item: id = msg_send![item, init];
+    let types = types
+        .iter()
+        .map(|t| NSString::new(t).0)
+        .collect::<Vec<id>>();
+    let _: () = msg_send![item, setDataProvider: data_provider forTypes: NSArray::arrayWithObjects(nil, &types)];
+
+    let drag_item: id = msg_send![class!(NSDraggingItem), alloc];
+    msg_send![drag_item, initWithPasteboardWriter: item]
+}
+
+/// Adds a dragging item per virtual file, writing the file when the drop target asks for its URL.
+unsafe fn add_virtual_file_dragging_items(dragging_items: id, store: &Arc<VirtualFileStore>) {
+    for index in 0..store.len() {
+        let store = store.clone();
+        let item = data_provider_dragging_item(
+            Box::new(move |_| {
+                let path = store.materialize(index).ok()?;
+                Some(crate::uri::from_path(&path, None).ok()?.into_bytes())
+            }),
+            &["public.file-url".to_string()],
+        );
+        let _: () = msg_send![dragging_items, addObject: item];
+    }
+}
+
+/// Creates a dragging item writing the given strings to the pasteboard, keyed by pasteboard type.
+unsafe fn string_dragging_item(strings: &[(id, &str)]) -> id {
+    let item: id = msg_send![class!(NSPasteboardItem), alloc];
+    let item: id = msg_send![item, init];
+    for (pasteboard_type, string) in strings {
+        let _: BOOL = msg_send![item, setString: NSString::new(string).0 forType: *pasteboard_type];
+    }
+
+    let drag_item: id = msg_send![class!(NSDraggingItem), alloc];
+    msg_send![drag_item, initWithPasteboardWriter: item]
+}
+
+unsafe fn tracker(source: &Object) -> &DragTracker {
+    &*(*source.get_ivar::<*mut c_void>("tracker_ptr") as *const DragTracker)
+}
+
+fn cursor_position(screen_point: NSPoint) -> CursorPosition {
+    CursorPosition {
+        x: screen_point.x as i32,
+        y: CGDisplay::main().pixels_high() as i32 - screen_point.y as i32,
+    }
+}
+
+fn operation_from_drag_action(action: DragAction) -> NSUInteger {
+    match action {
+        DragAction::Copy => NS_DRAG_OPERATION_COPY,
+        DragAction::Move => NS_DRAG_OPERATION_MOVE,
+        DragAction::Link => NS_DRAG_OPERATION_LINK,
+    }
+}
+
+fn drag_action_from_operation(operation: NSUInteger) -> DragAction {
+    if operation & NS_DRAG_OPERATION_MOVE != 0 {
+        DragAction::Move
+    } else if operation & NS_DRAG_OPERATION_LINK != 0 {
+        DragAction::Link
+    } else {
+        DragAction::Copy
+    }
+}
@@ -12,4 +12,11 @@ mod platform;
 #[path = "macos/mod.rs"]
 mod platform;
 
+pub(crate) use platform::auto_preview;
+#[cfg(all(feature = "async", not(target_os = "linux")))]
+pub use platform::run_on_main_thread;
 pub use platform::start_drag;
+#[cfg(target_os = "linux")]
+pub use platform::DropTarget;
+#[cfg(not(feature = "image-decode"))]
+pub(crate) use platform::{prepare_image, validate_image};
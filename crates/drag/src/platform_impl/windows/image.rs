@@ -4,14 +4,15 @@
 
 use std::os::windows::ffi::OsStrExt;
 use std::{ffi::c_void, iter::once, path::Path};
-use windows::core::PCWSTR;
+use windows::core::{ComInterface, PCWSTR};
 use windows::Win32::Foundation::*;
 use windows::Win32::{
     Graphics::{
-        Gdi::{CreateBitmap, HBITMAP},
+        Gdi::{CreateBitmap, HBITMAP, HPALETTE},
         Imaging::{
             CLSID_WICImagingFactory, GUID_WICPixelFormat32bppPBGRA, IWICBitmapDecoder,
-            IWICImagingFactory, WICConvertBitmapSource, WICDecodeMetadataCacheOnDemand,
+            IWICBitmapSource, IWICImagingFactory, WICBitmapInterpolationModeFant,
+            WICBitmapUsePremultipliedAlpha, WICConvertBitmapSource, WICDecodeMetadataCacheOnDemand,
         },
     },
     System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
@@ -19,10 +20,9 @@ use windows::Win32::{
 
 use crate::Result;
 
-pub(crate) fn read_bytes_to_hbitmap(bytes: &[u8]) -> Result<HBITMAP> {
+pub(crate) fn read_bytes_to_source(bytes: &[u8]) -> Result<IWICBitmapSource> {
     unsafe {
-        let factory: IWICImagingFactory =
-            CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER)?;
+        let factory = imaging_factory()?;
 
         let stream = factory.CreateStream()?;
         stream.InitializeFromMemory(bytes)?;
@@ -33,14 +33,13 @@ pub(crate) fn read_bytes_to_hbitmap(bytes: &[u8]) -> Result<HBITMAP> {
             WICDecodeMetadataCacheOnDemand,
         )?;
 
-        decoder_to_hbitmap(decoder)
+        decoder_to_source(decoder)
     }
 }
 
-pub(crate) fn read_path_to_hbitmap(path: &Path) -> Result<HBITMAP> {
+pub(crate) fn read_path_to_source(path: &Path) -> Result<IWICBitmapSource> {
     unsafe {
-        let factory: IWICImagingFactory =
-            CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER)?;
+        let factory = imaging_factory()?;
 
         let path = dunce::canonicalize(path)?;
         let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
@@ -52,25 +51,52 @@ pub(crate) fn read_path_to_hbitmap(path: &Path) -> Result<HBITMAP> {
             WICDecodeMetadataCacheOnDemand,
         )?;
 
-        decoder_to_hbitmap(decoder)
+        decoder_to_source(decoder)
     }
 }
 
-fn decoder_to_hbitmap(decoder: IWICBitmapDecoder) -> Result<HBITMAP> {
+pub(crate) fn rgba_to_source(
+    width: u32,
+    height: u32,
+    stride: u32,
+    pixels: &[u8],
+) -> Result<IWICBitmapSource> {
+    let pixel_buf = crate::image::rgba_to_premultiplied_bgra(width, height, stride, pixels);
     unsafe {
-        let frame = decoder.GetFrame(0)?;
+        let bitmap = imaging_factory()?.CreateBitmapFromMemory(
+            width,
+            height,
+            &GUID_WICPixelFormat32bppPBGRA,
+            width * 4,
+            &pixel_buf,
+        )?;
+        Ok(WICConvertBitmapSource(
+            &GUID_WICPixelFormat32bppPBGRA,
+            &bitmap,
+        )?)
+    }
+}
 
-        let mut width: u32 = 0;
-        let mut height: u32 = 0;
-        frame.GetSize(&mut width, &mut height)?;
+/// Scales the premultiplied BGRA source to the given size and applies the opacity.
+pub(crate) fn source_to_hbitmap(
+    source: &IWICBitmapSource,
+    width: u32,
+    height: u32,
+    opacity: f64,
+) -> Result<HBITMAP> {
+    unsafe {
+        let scaler = imaging_factory()?.CreateBitmapScaler()?;
+        scaler.Initialize(source, width, height, WICBitmapInterpolationModeFant)?;
 
         let mut pixel_buf: Vec<u8> = vec![0; (width * height * 4) as usize];
-        let pixel_format = frame.GetPixelFormat()?;
-        if pixel_format != GUID_WICPixelFormat32bppPBGRA {
-            let bitmap_source = WICConvertBitmapSource(&GUID_WICPixelFormat32bppPBGRA, &frame)?;
-            bitmap_source.CopyPixels(std::ptr::null(), width * 4, &mut pixel_buf)?;
-        } else {
-            frame.CopyPixels(std::ptr::null(), width * 4, &mut pixel_buf)?;
+        scaler.CopyPixels(std::ptr::null(), width * 4, &mut pixel_buf)?;
+
+        // the color channels are premultiplied, so all of them scale with the alpha
+        if opacity < 1. {
+            let opacity = opacity.max(0.);
+            for channel in &mut pixel_buf {
+                *channel = (*channel as f64 * opacity).round() as u8;
+            }
         }
 
         Ok(CreateBitmap(
@@ -82,3 +108,50 @@ fn decoder_to_hbitmap(decoder: IWICBitmapDecoder) -> Result<HBITMAP> {
         ))
     }
 }
+
+/// Copies the pixels of the bitmap, which has premultiplied alpha, as RGBA pixels with straight alpha.
+pub(crate) fn hbitmap_to_rgba(hbitmap: HBITMAP) -> Result<(u32, u32, Vec<u8>)> {
+    unsafe {
+        let bitmap = imaging_factory()?.CreateBitmapFromHBITMAP(
+            hbitmap,
+            HPALETTE::default(),
+            WICBitmapUsePremultipliedAlpha,
+        )?;
+        source_to_rgba(&bitmap.cast()?)
+    }
+}
+
+/// Copies the pixels of the source as RGBA pixels with straight alpha.
+pub(crate) fn source_to_rgba(source: &IWICBitmapSource) -> Result<(u32, u32, Vec<u8>)> {
+    use windows::Win32::Graphics::Imaging::GUID_WICPixelFormat32bppRGBA;
+
+    unsafe {
+        let source = WICConvertBitmapSource(&GUID_WICPixelFormat32bppRGBA, source)?;
+
+        let mut width: u32 = 0;
+        let mut height: u32 = 0;
+        source.GetSize(&mut width, &mut height)?;
+
+        let mut pixel_buf: Vec<u8> = vec![0; (width * height * 4) as usize];
+        source.CopyPixels(std::ptr::null(), width * 4, &mut pixel_buf)?;
+        Ok((width, height, pixel_buf))
+    }
+}
+
+unsafe fn imaging_factory() -> Result<IWICImagingFactory> {
+    Ok(CoCreateInstance(
+        &CLSID_WICImagingFactory,
+        None,
+        CLSCTX_INPROC_SERVER,
+    )?)
+}
+
+fn decoder_to_source(decoder: IWICBitmapDecoder) -> Result<IWICBitmapSource> {
+    unsafe {
+        let frame = decoder.GetFrame(0)?;
+        Ok(WICConvertBitmapSource(
+            &GUID_WICPixelFormat32bppPBGRA,
+            &frame,
+        )?)
+    }
+}
@@ -4,31 +4,49 @@
 
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 
-use crate::{CursorPosition, DragItem, DragResult, Image, Options};
+use crate::{
+    session::DragEmitter, virtual_files::VirtualFileStore, CancelReason, Composite, CursorPosition,
+    DragAction, DragEvent, DragFile, DragImage, DragItem, DragResult, DragSession, Image, Options,
+    Representation, VirtualFile,
+};
 
 use std::{
+    cell::Cell,
     ffi::c_void,
     iter::once,
     os::windows::ffi::OsStrExt,
     path::{Path, PathBuf},
-    sync::Once,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
 };
 use windows::{
     core::*,
     Win32::{
         Foundation::*,
-        Graphics::Gdi::{GetObjectW, BITMAP},
+        Graphics::{Gdi::DeleteObject, Imaging::IWICBitmapSource},
         System::Com::*,
+        System::DataExchange::RegisterClipboardFormatW,
         System::Memory::*,
-        System::Ole::{DoDragDrop, OleInitialize},
-        System::Ole::{IDropSource, IDropSource_Impl, CF_HDROP, DROPEFFECT, DROPEFFECT_COPY},
+        System::Ole::{DoDragDrop, OleInitialize, ReleaseStgMedium},
+        System::Ole::{
+            IDropSource, IDropSource_Impl, CF_HDROP, CF_UNICODETEXT, DROPEFFECT, DROPEFFECT_COPY,
+            DROPEFFECT_LINK, DROPEFFECT_MOVE, DROPEFFECT_NONE,
+        },
         System::SystemServices::{MK_LBUTTON, MODIFIERKEYS_FLAGS},
         UI::{
+            HiDpi::GetDpiForWindow,
             Shell::{
                 BHID_DataObject, CLSID_DragDropHelper, Common, IDragSourceHelper, IShellItemArray,
-                SHCreateDataObject, SHCreateShellItemArrayFromIDLists, DROPFILES, SHDRAGIMAGE,
+                IShellItemImageFactory, SHCreateDataObject, SHCreateItemFromParsingName,
+                SHCreateShellItemArrayFromIDLists, SHCreateStdEnumFmtEtc, CFSTR_FILECONTENTS,
+                CFSTR_FILEDESCRIPTORW, CFSTR_LOGICALPERFORMEDDROPEFFECT, CFSTR_PERFORMEDDROPEFFECT,
+                DROPFILES, FD_PROGRESSUI, FD_UNICODE, FILEDESCRIPTORW, SHDRAGIMAGE,
+                SIIGBF_RESIZETOFIT,
             },
-            WindowsAndMessaging::GetCursorPos,
+            WindowsAndMessaging::{GetCursorPos, GetWindowRect, USER_DEFAULT_SCREEN_DPI},
         },
     },
 };
@@ -53,22 +71,107 @@ struct DataObject {
     inner_shell_obj: IDataObject,
 }
 
+/// Offers virtual files through `CFSTR_FILEDESCRIPTORW` and `CFSTR_FILECONTENTS`,
+/// writing each file when the drop target asks for its contents.
+#[implement(IDataObject)]
+struct VirtualFilesDataObject {
+    store: Arc<VirtualFileStore>,
+    /// The serialized `FILEGROUPDESCRIPTORW`.
+    descriptors: Vec<u8>,
+    descriptor_format: u16,
+    contents_format: u16,
+    inner_shell_obj: IDataObject,
+}
+
+/// Reports the progress of the drag operation from the drop source callbacks.
+struct DragTracker {
+    hwnd: HWND,
+    emitter: DragEmitter,
+    last_position: Cell<Option<CursorPosition>>,
+    inside_window: Cell<bool>,
+    effect: Cell<Option<DROPEFFECT>>,
+}
+
+impl DragTracker {
+    fn new(hwnd: HWND, emitter: DragEmitter) -> Self {
+        Self {
+            hwnd,
+            emitter,
+            last_position: Cell::new(None),
+            inside_window: Cell::new(true),
+            effect: Cell::new(None),
+        }
+    }
+
+    fn emit(&self, event: DragEvent) {
+        self.emitter.emit(event);
+    }
+
+    fn update_position(&self) {
+        let mut pt = POINT { x: 0, y: 0 };
+        if unsafe { GetCursorPos(&mut pt) }.is_err() {
+            return;
+        }
+
+        let position = CursorPosition { x: pt.x, y: pt.y };
+        if self.last_position.replace(Some(position)) != Some(position) {
+            self.emit(DragEvent::Moved(position));
+        }
+
+        let mut rect = RECT::default();
+        let inside_window = unsafe { GetWindowRect(self.hwnd, &mut rect) }.is_ok()
+            && pt.x >= rect.left
+            && pt.x < rect.right
+            && pt.y >= rect.top
+            && pt.y < rect.bottom;
+        if self.inside_window.replace(inside_window) != inside_window {
+            self.emit(if inside_window {
+                DragEvent::ReenteredSourceWindow
+            } else {
+                DragEvent::LeftSourceWindow
+            });
+        }
+    }
+
+    fn update_effect(&self, effect: DROPEFFECT) {
+        let effect = effect & (DROPEFFECT_COPY | DROPEFFECT_MOVE | DROPEFFECT_LINK);
+        if self.effect.replace(Some(effect)) != Some(effect) {
+            self.emit(DragEvent::ActionChanged(if effect == DROPEFFECT_NONE {
+                None
+            } else {
+                Some(drag_action_from_drop_effect(effect))
+            }));
+        }
+    }
+}
+
 #[implement(IDropSource)]
-struct DropSource(());
+struct DropSource {
+    escape_pressed: Arc<AtomicBool>,
+    tracker: Rc<DragTracker>,
+}
 
 #[implement(IDropSource)]
-struct DummyDropSource(());
+struct DummyDropSource {
+    escape_pressed: Arc<AtomicBool>,
+    tracker: Rc<DragTracker>,
+}
 
 impl DropSource {
-    fn new() -> Self {
-        Self(())
+    fn new(escape_pressed: Arc<AtomicBool>, tracker: Rc<DragTracker>) -> Self {
+        Self {
+            escape_pressed,
+            tracker,
+        }
     }
 }
 
 #[allow(non_snake_case)]
 impl IDropSource_Impl for DropSource {
     fn QueryContinueDrag(&self, fescapepressed: BOOL, grfkeystate: MODIFIERKEYS_FLAGS) -> HRESULT {
+        self.tracker.update_position();
         if fescapepressed.as_bool() {
+            self.escape_pressed.store(true, Ordering::Relaxed);
             DRAGDROP_S_CANCEL
         } else if (grfkeystate & MK_LBUTTON) == MODIFIERKEYS_FLAGS(0) {
             DRAGDROP_S_DROP
@@ -77,28 +180,37 @@ impl IDropSource_Impl for DropSource {
         }
     }
 
-    fn GiveFeedback(&self, _dweffect: DROPEFFECT) -> HRESULT {
+    fn GiveFeedback(&self, dweffect: DROPEFFECT) -> HRESULT {
+        self.tracker.update_effect(dweffect);
         DRAGDROP_S_USEDEFAULTCURSORS
     }
 }
 
 impl DummyDropSource {
-    fn new() -> Self {
-        Self(())
+    fn new(escape_pressed: Arc<AtomicBool>, tracker: Rc<DragTracker>) -> Self {
+        Self {
+            escape_pressed,
+            tracker,
+        }
     }
 }
 
 #[allow(non_snake_case)]
 impl IDropSource_Impl for DummyDropSource {
     fn QueryContinueDrag(&self, fescapepressed: BOOL, grfkeystate: MODIFIERKEYS_FLAGS) -> HRESULT {
-        if fescapepressed.as_bool() || (grfkeystate & MK_LBUTTON) == MODIFIERKEYS_FLAGS(0) {
+        self.tracker.update_position();
+        if fescapepressed.as_bool() {
+            self.escape_pressed.store(true, Ordering::Relaxed);
+            DRAGDROP_S_CANCEL
+        } else if (grfkeystate & MK_LBUTTON) == MODIFIERKEYS_FLAGS(0) {
             DRAGDROP_S_CANCEL
         } else {
             S_OK
         }
     }
 
-    fn GiveFeedback(&self, _dweffect: DROPEFFECT) -> HRESULT {
+    fn GiveFeedback(&self, dweffect: DROPEFFECT) -> HRESULT {
+        self.tracker.update_effect(dweffect);
         DRAGDROP_S_USEDEFAULTCURSORS
     }
 }
@@ -210,134 +322,647 @@ impl IDataObject_Impl for DataObject {
     }
 }
 
+impl VirtualFilesDataObject {
+    fn new(store: Arc<VirtualFileStore>, descriptors: Vec<u8>) -> Result<Self> {
+        unsafe {
+            Ok(Self {
+                store,
+                descriptors,
+                descriptor_format: RegisterClipboardFormatW(CFSTR_FILEDESCRIPTORW) as u16,
+                contents_format: RegisterClipboardFormatW(CFSTR_FILECONTENTS) as u16,
+                inner_shell_obj: SHCreateDataObject(None, None, None)?,
+            })
+        }
+    }
+
+    fn formats(&self) -> [FORMATETC; 2] {
+        [self.descriptor_format, self.contents_format].map(|format| FORMATETC {
+            cfFormat: format,
+            ptd: std::ptr::null_mut(),
+            dwAspect: DVASPECT_CONTENT.0,
+            lindex: -1,
+            tymed: TYMED_HGLOBAL.0 as u32,
+        })
+    }
+
+    /// Checks whether the format is one of ours, returning `None` for the formats of the inner data object.
+    fn check_format(&self, format_etc: &FORMATETC) -> Option<HRESULT> {
+        if format_etc.cfFormat != self.descriptor_format
+            && format_etc.cfFormat != self.contents_format
+        {
+            return None;
+        }
+        Some(if format_etc.tymed & TYMED_HGLOBAL.0 as u32 == 0 {
+            DV_E_TYMED
+        } else if format_etc.cfFormat == self.contents_format
+            && !(0..self.store.len() as i32).contains(&format_etc.lindex)
+        {
+            DV_E_LINDEX
+        } else {
+            S_OK
+        })
+    }
+
+    fn data(&self, format_etc: &FORMATETC) -> crate::Result<Vec<u8>> {
+        if format_etc.cfFormat == self.descriptor_format {
+            Ok(self.descriptors.clone())
+        } else {
+            let path = self.store.materialize(format_etc.lindex as usize)?;
+            Ok(std::fs::read(path)?)
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+impl IDataObject_Impl for VirtualFilesDataObject {
+    fn GetData(&self, pformatetc: *const FORMATETC) -> Result<STGMEDIUM> {
+        let Some(format_etc) = (unsafe { pformatetc.as_ref() }) else {
+            return Err(Error::new(E_INVALIDARG, HSTRING::new()));
+        };
+        match self.check_format(format_etc) {
+            None => unsafe { self.inner_shell_obj.GetData(pformatetc) },
+            Some(S_OK) => {
+                let data = self
+                    .data(format_etc)
+                    .map_err(|e| Error::new(E_FAIL, HSTRING::from(e.to_string())))?;
+                Ok(STGMEDIUM {
+                    tymed: TYMED_HGLOBAL.0 as u32,
+                    u: STGMEDIUM_0 {
+                        hGlobal: global_bytes(&data)?,
+                    },
+                    pUnkForRelease: std::mem::ManuallyDrop::new(None),
+                })
+            }
+            Some(error) => Err(Error::new(error, HSTRING::new())),
+        }
+    }
+
+    fn GetDataHere(&self, _pformatetc: *const FORMATETC, _pmedium: *mut STGMEDIUM) -> Result<()> {
+        Err(Error::new(DV_E_FORMATETC, HSTRING::new()))
+    }
+
+    fn QueryGetData(&self, pformatetc: *const FORMATETC) -> HRESULT {
+        match unsafe { pformatetc.as_ref() }.map(|format_etc| self.check_format(format_etc)) {
+            None => E_INVALIDARG,
+            Some(None) => unsafe { self.inner_shell_obj.QueryGetData(pformatetc) },
+            Some(Some(result)) => result,
+        }
+    }
+
+    fn GetCanonicalFormatEtc(
+        &self,
+        _pformatectin: *const FORMATETC,
+        pformatetcout: *mut FORMATETC,
+    ) -> HRESULT {
+        unsafe { (*pformatetcout).ptd = std::ptr::null_mut() };
+        E_NOTIMPL
+    }
+
+    fn SetData(
+        &self,
+        pformatetc: *const FORMATETC,
+        pmedium: *const STGMEDIUM,
+        frelease: BOOL,
+    ) -> Result<()> {
+        unsafe { self.inner_shell_obj.SetData(pformatetc, pmedium, frelease) }
+    }
+
+    // drop targets look for the file descriptors in the enumerated formats
+    fn EnumFormatEtc(&self, dwdirection: u32) -> Result<IEnumFORMATETC> {
+        if dwdirection == DATADIR_GET.0 as u32 {
+            unsafe { SHCreateStdEnumFmtEtc(&self.formats()) }
+        } else {
+            Err(Error::new(E_NOTIMPL, HSTRING::new()))
+        }
+    }
+
+    fn DAdvise(
+        &self,
+        _pformatetc: *const FORMATETC,
+        _advf: u32,
+        _padvsink: Option<&IAdviseSink>,
+    ) -> Result<u32> {
+        Err(Error::new(OLE_E_ADVISENOTSUPPORTED, HSTRING::new()))
+    }
+
+    fn DUnadvise(&self, _dwconnection: u32) -> Result<()> {
+        Err(Error::new(OLE_E_ADVISENOTSUPPORTED, HSTRING::new()))
+    }
+
+    fn EnumDAdvise(&self) -> Result<IEnumSTATDATA> {
+        Err(Error::new(OLE_E_ADVISENOTSUPPORTED, HSTRING::new()))
+    }
+}
+
 pub fn start_drag<W: HasRawWindowHandle, F: Fn(DragResult, CursorPosition) + Send + 'static>(
     handle: &W,
     item: DragItem,
-    image: Image,
+    image: impl Into<DragImage>,
     on_drop_callback: F,
-    _options: Options,
-) -> crate::Result<()> {
-    if let RawWindowHandle::Win32(_w) = handle.raw_window_handle() {
-        match item {
+    mut options: Options,
+) -> crate::Result<DragSession> {
+    let item = crate::path::normalize_item(item, &options)?;
+    let image = crate::image::resolve(image.into(), &item, options.strict_image)?;
+    if let RawWindowHandle::Win32(w) = handle.raw_window_handle() {
+        let tracker = Rc::new(DragTracker::new(
+            HWND(w.hwnd as isize),
+            DragEmitter::new(options.on_event.take()),
+        ));
+        let allowed_effects = options
+            .allowed_actions()
+            .iter()
+            .fold(DROPEFFECT(0), |effects, action| {
+                effects | drop_effect_from_action(*action)
+            });
+
+        init_ole();
+        unsafe {
+            if let Err(e) = &OLE_RESULT {
+                return Err(e.clone().into());
+            }
+        }
+        let drag_image = get_drag_image(image, tracker.hwnd, options.strict_image)?;
+
+        let escape_pressed = Arc::new(AtomicBool::new(false));
+        let (data_object, drop_source): (IDataObject, IDropSource) = match item {
             DragItem::Files(files) => {
-                init_ole();
-                unsafe {
-                    if let Err(e) = &OLE_RESULT {
-                        return Err(e.clone().into());
-                    }
+                let paths = display_paths(files, &tracker, &options)?;
+                (
+                    get_files_data_object(&paths, &options)?,
+                    DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+                )
+            }
+            DragItem::VirtualFiles(files) => (
+                get_virtual_files_data_object(files, &tracker, &options)?,
+                DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+            ),
+            // XDS is specific to X11, so the file is offered like a virtual file
+            DragItem::DirectSave(file) => (
+                get_virtual_files_data_object(vec![file], &tracker, &options)?,
+                DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+            ),
+            DragItem::Text(text) => (
+                get_data_object(&[(CF_UNICODETEXT.0, wide_bytes(&text))])?,
+                DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+            ),
+            DragItem::Uris(uris) => {
+                let mut formats =
+                    vec![(CF_UNICODETEXT.0, wide_bytes(&crate::uri::text_list(&uris)))];
+                if let Some(uri) = uris.first() {
+                    formats.push((url_clipboard_format(), wide_bytes(&uri.uri)));
                 }
-
-                let mut paths = Vec::new();
-                for f in files {
-                    paths.push(dunce::canonicalize(f)?);
+                (
+                    get_data_object(&formats)?,
+                    DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+                )
+            }
+            DragItem::Html { html, text } => {
+                let text = text.unwrap_or_else(|| crate::html::to_plain_text(&html));
+                (
+                    get_data_object(&[
+                        (html_clipboard_format(), cf_html(&html)),
+                        (CF_UNICODETEXT.0, wide_bytes(&text)),
+                    ])?,
+                    DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+                )
+            }
+            DragItem::Composite(Composite {
+                files,
+                text,
+                html,
+                types,
+                provider,
+            }) => {
+                let paths = display_paths(files, &tracker, &options)?;
+                let data_object = if paths.is_empty() {
+                    get_data_object(&[])?
+                } else {
+                    get_files_data_object(&paths, &options)?
+                };
+                if let Some(text) = text.or_else(|| html.as_deref().map(crate::html::to_plain_text))
+                {
+                    set_hglobal_data(&data_object, CF_UNICODETEXT.0, &wide_bytes(&text))?;
                 }
-
-                let data_object: IDataObject = get_file_data_object(&paths).unwrap();
-                let drop_source: IDropSource = DropSource::new().into();
-
-                unsafe {
-                    if let Some(drag_image) = get_drag_image(image) {
-                        if let Ok(helper) =
-                            create_instance::<IDragSourceHelper>(&CLSID_DragDropHelper)
-                        {
-                            let _ = helper.InitializeFromBitmap(&drag_image, &data_object);
-                        }
-                    }
-
-                    let mut out_dropeffect = DROPEFFECT::default();
-                    let drop_result = DoDragDrop(
-                        &data_object,
-                        &drop_source,
-                        DROPEFFECT_COPY,
-                        &mut out_dropeffect,
-                    );
-                    let mut pt = POINT { x: 0, y: 0 };
-                    GetCursorPos(&mut pt)?;
-                    if drop_result == DRAGDROP_S_DROP {
-                        on_drop_callback(DragResult::Dropped, CursorPosition { x: pt.x, y: pt.y });
-                    } else {
-                        // DRAGDROP_S_CANCEL
-                        on_drop_callback(DragResult::Cancel, CursorPosition { x: pt.x, y: pt.y });
+                if let Some(html) = html {
+                    set_hglobal_data(&data_object, html_clipboard_format(), &cf_html(&html))?;
+                }
+                // the data object can't generate the data on demand, so the provider is called upfront
+                for data_type in &types {
+                    if let Some(data) = provider(Representation::Data(data_type)) {
+                        let format =
+                            unsafe { RegisterClipboardFormatW(&HSTRING::from(data_type.as_str())) };
+                        set_hglobal_data(&data_object, format as u16, &data)?;
                     }
                 }
+                (
+                    data_object,
+                    DropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+                )
             }
             DragItem::Data { .. } => {
-                init_ole();
-                unsafe {
-                    if let Err(e) = &OLE_RESULT {
-                        return Err(e.clone().into());
-                    }
-                }
-
                 let paths = vec![dunce::canonicalize("./")?];
 
-                let data_object: IDataObject = get_file_data_object(&paths).unwrap();
-                let drop_source: IDropSource = DummyDropSource::new().into();
-
-                unsafe {
-                    if let Some(drag_image) = get_drag_image(image) {
-                        if let Ok(helper) =
-                            create_instance::<IDragSourceHelper>(&CLSID_DragDropHelper)
-                        {
-                            let _ = helper.InitializeFromBitmap(&drag_image, &data_object);
-                        }
-                    }
+                (
+                    get_file_data_object(&paths)?,
+                    DummyDropSource::new(escape_pressed.clone(), tracker.clone()).into(),
+                )
+            }
+        };
 
-                    let mut out_dropeffect = DROPEFFECT::default();
-                    let drop_result = DoDragDrop(
-                        &data_object,
-                        &drop_source,
-                        DROPEFFECT_COPY,
-                        &mut out_dropeffect,
-                    );
-                    let mut pt = POINT { x: 0, y: 0 };
-                    GetCursorPos(&mut pt)?;
-                    if drop_result == DRAGDROP_S_DROP {
-                        on_drop_callback(DragResult::Dropped, CursorPosition { x: pt.x, y: pt.y });
-                    } else {
-                        // DRAGDROP_S_CANCEL
-                        on_drop_callback(DragResult::Cancel, CursorPosition { x: pt.x, y: pt.y });
-                    }
+        unsafe {
+            if let Some(drag_image) = drag_image {
+                if let Ok(helper) = create_instance::<IDragSourceHelper>(&CLSID_DragDropHelper) {
+                    let _ = helper.InitializeFromBitmap(&drag_image, &data_object);
                 }
             }
+
+            tracker.emit(DragEvent::Started);
+            let mut out_dropeffect = DROPEFFECT::default();
+            let drop_result = DoDragDrop(
+                &data_object,
+                &drop_source,
+                allowed_effects,
+                &mut out_dropeffect,
+            );
+            let mut pt = POINT { x: 0, y: 0 };
+            GetCursorPos(&mut pt)?;
+            let result = get_drag_result(
+                drop_result,
+                out_dropeffect,
+                performed_drop_effect(&data_object),
+                escape_pressed.load(Ordering::Relaxed),
+            );
+            tracker.emit(DragEvent::Finished(result));
+            on_drop_callback(result, CursorPosition { x: pt.x, y: pt.y });
         }
-        Ok(())
+        Ok(tracker.emitter.session(None))
     } else {
         Err(crate::Error::UnsupportedWindowHandle)
     }
 }
 
-fn get_drag_image(image: Image) -> Option<SHDRAGIMAGE> {
-    let hbitmap = match image {
-        Image::Raw(bytes) => image::read_bytes_to_hbitmap(&bytes).ok(),
-        Image::File(path) => image::read_path_to_hbitmap(&path).ok(),
+/// Runs the task on the thread that owns the window.
+///
+/// The task is posted to a message-only window owned by that thread, see [`task_window`].
+#[cfg(feature = "async")]
+pub fn run_on_main_thread(
+    handle: &RawWindowHandle,
+    task: Box<dyn FnOnce() + Send>,
+) -> crate::Result<()> {
+    use windows::Win32::{
+        System::Threading::GetCurrentThreadId,
+        UI::WindowsAndMessaging::{GetWindowThreadProcessId, PostMessageW, WM_USER},
+    };
+
+    let RawWindowHandle::Win32(w) = handle else {
+        return Err(crate::Error::UnsupportedWindowHandle);
+    };
+    let hwnd = HWND(w.hwnd as isize);
+
+    unsafe {
+        let thread_id = GetWindowThreadProcessId(hwnd, None);
+        if thread_id == GetCurrentThreadId() {
+            task();
+            return Ok(());
+        }
+
+        let task_window = task_window(hwnd, thread_id)?;
+        let task = Box::into_raw(Box::new(task));
+        if let Err(e) = PostMessageW(task_window, WM_USER, WPARAM(0), LPARAM(task as isize)) {
+            drop(Box::from_raw(task));
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The message-only window running the tasks on the thread owning `hwnd`, created on first use.
+///
+/// Only the owning thread can create the window, so a `WH_CALLWNDPROC` hook is installed on it
+/// for a single message sent to `hwnd`, and removed right after.
+#[cfg(feature = "async")]
+unsafe fn task_window(hwnd: HWND, thread_id: u32) -> crate::Result<HWND> {
+    use std::sync::Mutex;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        GetWindowThreadProcessId, IsWindow, SendMessageW, SetWindowsHookExW, UnhookWindowsHookEx,
+        WH_CALLWNDPROC,
+    };
+
+    static TASK_WINDOWS: Mutex<Vec<isize>> = Mutex::new(Vec::new());
+
+    {
+        let mut task_windows = TASK_WINDOWS.lock().unwrap();
+        // the windows of a thread are destroyed when it exits, and its id can then be reused by another thread
+        task_windows.retain(|window| IsWindow(HWND(*window)).as_bool());
+        if let Some(window) = task_windows
+            .iter()
+            .find(|window| GetWindowThreadProcessId(HWND(**window), None) == thread_id)
+        {
+            return Ok(HWND(*window));
+        }
+    }
+
+    let hook = SetWindowsHookExW(
+        WH_CALLWNDPROC,
+        Some(create_task_window_hook),
+        HINSTANCE(0),
+        thread_id,
+    )?;
+    let mut window = HWND(0);
+    // returns once the owning thread processed the message
+    SendMessageW(
+        hwnd,
+        create_task_window_message(),
+        WPARAM(0),
+        LPARAM(&mut window as *mut HWND as isize),
+    );
+    let _ = UnhookWindowsHookEx(hook);
+
+    if window.0 == 0 {
+        return Err(Error::new(E_FAIL, HSTRING::from("failed to create the task window")).into());
+    }
+    TASK_WINDOWS.lock().unwrap().push(window.0);
+    Ok(window)
+}
+
+#[cfg(feature = "async")]
+fn create_task_window_message() -> u32 {
+    use windows::Win32::UI::WindowsAndMessaging::RegisterWindowMessageW;
+
+    static MESSAGE: std::sync::OnceLock<u32> = std::sync::OnceLock::new();
+    *MESSAGE.get_or_init(|| unsafe { RegisterWindowMessageW(w!("DragRsCreateTaskWindow")) })
+}
+
+#[cfg(feature = "async")]
+unsafe extern "system" fn create_task_window_hook(
+    code: i32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    use windows::Win32::{
+        System::LibraryLoader::GetModuleHandleW,
+        UI::WindowsAndMessaging::{
+            CallNextHookEx, CreateWindowExW, RegisterClassW, CWPSTRUCT, HC_ACTION, HHOOK, HMENU,
+            HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE, WNDCLASSW,
+        },
     };
-    hbitmap.map(|hbitmap| unsafe {
-        // get image size
-        let mut bitmap: BITMAP = BITMAP::default();
-        let (width, height) = if 0
-            == GetObjectW(
-                hbitmap,
-                std::mem::size_of::<BITMAP>() as i32,
-                Some(&mut bitmap as *mut BITMAP as *mut c_void),
-            ) {
-            (128, 128)
+
+    if code == HC_ACTION as i32 {
+        let message = &*(lparam.0 as *const CWPSTRUCT);
+        if message.message == create_task_window_message() {
+            let instance = HINSTANCE::from(GetModuleHandleW(None).unwrap_or_default());
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(task_window_proc),
+                hInstance: instance,
+                lpszClassName: w!("DragRsTaskWindow"),
+                ..Default::default()
+            };
+            // fails once the class is registered
+            RegisterClassW(&class);
+            *(message.lParam.0 as *mut HWND) = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("DragRsTaskWindow"),
+                PCWSTR::null(),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                HMENU(0),
+                instance,
+                None,
+            );
+        }
+    }
+    CallNextHookEx(HHOOK(0), code, wparam, lparam)
+}
+
+#[cfg(feature = "async")]
+unsafe extern "system" fn task_window_proc(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    use windows::Win32::UI::WindowsAndMessaging::{DefWindowProcW, WM_USER};
+
+    if message == WM_USER {
+        let task = Box::from_raw(lparam.0 as *mut Box<dyn FnOnce() + Send>);
+        task();
+        return LRESULT(0);
+    }
+    DefWindowProcW(hwnd, message, wparam, lparam)
+}
+
+fn get_drag_result(
+    drop_result: HRESULT,
+    effect: DROPEFFECT,
+    performed_effect: Option<DROPEFFECT>,
+    escape_pressed: bool,
+) -> DragResult {
+    if drop_result == DRAGDROP_S_DROP {
+        let effect = performed_effect
+            .filter(|effect| *effect != DROPEFFECT_NONE)
+            .unwrap_or(effect)
+            & (DROPEFFECT_COPY | DROPEFFECT_MOVE | DROPEFFECT_LINK);
+        // the drop target refused the data
+        if effect == DROPEFFECT_NONE {
+            DragResult::Cancel(CancelReason::NoTarget)
+        } else {
+            DragResult::Dropped(drag_action_from_drop_effect(effect))
+        }
+    } else if drop_result == DRAGDROP_S_CANCEL {
+        if escape_pressed {
+            DragResult::Cancel(CancelReason::UserCancelled)
         } else {
-            (bitmap.bmWidth, bitmap.bmHeight)
+            DragResult::Cancel(CancelReason::NoTarget)
+        }
+    } else {
+        DragResult::Cancel(CancelReason::Error)
+    }
+}
+
+/// The effect the drop target stored in the data object.
+///
+/// Targets performing an optimized move, where they moved the files themselves, return `DROPEFFECT_NONE`
+/// from `DoDragDrop` and only report the move in the logical performed effect.
+fn performed_drop_effect(data_object: &IDataObject) -> Option<DROPEFFECT> {
+    [CFSTR_LOGICALPERFORMEDDROPEFFECT, CFSTR_PERFORMEDDROPEFFECT]
+        .into_iter()
+        .find_map(|format| unsafe {
+            let format_etc = FORMATETC {
+                cfFormat: RegisterClipboardFormatW(format) as u16,
+                ptd: std::ptr::null_mut(),
+                dwAspect: DVASPECT_CONTENT.0,
+                lindex: -1,
+                tymed: TYMED_HGLOBAL.0 as u32,
+            };
+            let mut medium = data_object.GetData(&format_etc).ok()?;
+            let ptr = GlobalLock(medium.u.hGlobal) as *const u32;
+            let effect = (!ptr.is_null()).then(|| DROPEFFECT(*ptr));
+            let _ = GlobalUnlock(medium.u.hGlobal);
+            ReleaseStgMedium(&mut medium);
+            effect
+        })
+}
+
+fn drop_effect_from_action(action: DragAction) -> DROPEFFECT {
+    match action {
+        DragAction::Copy => DROPEFFECT_COPY,
+        DragAction::Move => DROPEFFECT_MOVE,
+        DragAction::Link => DROPEFFECT_LINK,
+    }
+}
+
+fn drag_action_from_drop_effect(effect: DROPEFFECT) -> DragAction {
+    if effect.contains(DROPEFFECT_MOVE) {
+        DragAction::Move
+    } else if effect.contains(DROPEFFECT_LINK) {
+        DragAction::Link
+    } else {
+        DragAction::Copy
+    }
+}
+
+fn get_drag_image(
+    drag_image: DragImage,
+    hwnd: HWND,
+    strict: bool,
+) -> crate::Result<Option<SHDRAGIMAGE>> {
+    // only the first preview is shown
+    let preview = drag_image.image.into_previews().into_iter().take(1);
+    let mut drag_images = crate::image::load_previews(preview, strict, |preview| {
+        let source = image_source(&preview)?;
+
+        let (mut width, mut height) = (0, 0);
+        unsafe { source.GetSize(&mut width, &mut height) }?;
+        let (width, height) =
+            crate::image::logical_size(width, height, drag_image.scale_factor, drag_image.max_size);
+        // the drag image is shown in physical pixels
+        let dpi_scale = match unsafe { GetDpiForWindow(hwnd) } {
+            0 => 1.,
+            dpi => dpi as f64 / USER_DEFAULT_SCREEN_DPI as f64,
+        };
+        let size = SIZE {
+            cx: ((width * dpi_scale).round() as i32).max(1),
+            cy: ((height * dpi_scale).round() as i32).max(1),
         };
+        let hbitmap =
+            image::source_to_hbitmap(&source, size.cx as u32, size.cy as u32, drag_image.opacity)?;
+        let (hotspot_x, hotspot_y) = drag_image.hotspot.unwrap_or_default();
 
-        SHDRAGIMAGE {
-            sizeDragImage: SIZE {
-                cx: width,
-                cy: height,
+        Ok(SHDRAGIMAGE {
+            sizeDragImage: size,
+            ptOffset: POINT {
+                x: (hotspot_x as f64 * dpi_scale).round() as i32,
+                y: (hotspot_y as f64 * dpi_scale).round() as i32,
             },
-            ptOffset: POINT { x: 0, y: 0 },
             hbmpDragImage: hbitmap,
             crColorKey: COLORREF(0x00000000),
-        }
+        })
+    })?;
+    Ok(drag_images.pop())
+}
+
+#[cfg(not(feature = "image-decode"))]
+pub(crate) fn validate_image(image: &Image) -> crate::Result<()> {
+    with_com(|| image_source(image).map(drop))
+}
+
+#[cfg(not(feature = "image-decode"))]
+pub(crate) fn prepare_image(image: &Image) -> crate::Result<crate::PreparedImage> {
+    with_com(|| {
+        let (width, height, pixels) = image::source_to_rgba(&image_source(image)?)?;
+        Ok(crate::PreparedImage::from_rgba(
+            width,
+            height,
+            width * 4,
+            &pixels,
+        ))
     })
 }
 
+/// Gets the thumbnail or icon the shell shows for the file.
+pub(crate) fn auto_preview(path: &Path, size: u32) -> crate::Result<crate::PreparedImage> {
+    with_com(|| {
+        let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+        let (width, height, pixels) = unsafe {
+            let factory: IShellItemImageFactory =
+                SHCreateItemFromParsingName(PCWSTR::from_raw(wide_path.as_ptr()), None)
+                    .map_err(|e| crate::image::decode_error(Some(path), e))?;
+            let hbitmap = factory
+                .GetImage(
+                    SIZE {
+                        cx: size as i32,
+                        cy: size as i32,
+                    },
+                    SIIGBF_RESIZETOFIT,
+                )
+                .map_err(|e| crate::image::decode_error(Some(path), e))?;
+            let pixels = image::hbitmap_to_rgba(hbitmap);
+            DeleteObject(hbitmap);
+            pixels.map_err(|e| crate::image::decode_error(Some(path), e))?
+        };
+        Ok(crate::PreparedImage::from_rgba(
+            width,
+            height,
+            width * 4,
+            &pixels,
+        ))
+    })
+}
+
+/// Runs the function with COM initialized, which WIC needs, on threads that did not initialize it yet.
+fn with_com<T>(f: impl FnOnce() -> T) -> T {
+    let initialized = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.is_ok();
+    let result = f();
+    if initialized {
+        unsafe { CoUninitialize() };
+    }
+    result
+}
+
+fn image_source(image: &Image) -> crate::Result<IWICBitmapSource> {
+    match image {
+        Image::Raw(bytes) => {
+            image::read_bytes_to_source(bytes).map_err(|e| crate::image::decode_error(None, e))
+        }
+        Image::File(path) if !path.exists() => Err(crate::Error::ImageNotFound),
+        Image::File(path) => {
+            image::read_path_to_source(path).map_err(|e| crate::image::decode_error(Some(path), e))
+        }
+        Image::Rgba {
+            width,
+            height,
+            stride,
+            pixels,
+        } => {
+            crate::image::check_rgba(*width, *height, *stride, pixels)?;
+            image::rgba_to_source(*width, *height, *stride, pixels)
+                .map_err(|e| crate::image::decode_error(None, e))
+        }
+        Image::Prepared(prepared) => image::rgba_to_source(
+            prepared.width(),
+            prepared.height(),
+            prepared.stride(),
+            prepared.pixels(),
+        )
+        .map_err(|e| crate::image::decode_error(None, e)),
+        // flattened by `into_previews`, the first image is used otherwise
+        Image::Stack(images) => match images.first() {
+            Some(image) => image_source(image),
+            None => Err(crate::image::decode_error(None, "empty image stack")),
+        },
+        // replaced by the previews of the files, unless nested in a stack
+        Image::Auto => Err(crate::image::decode_error(
+            None,
+            "automatic previews can not be stacked",
+        )),
+    }
+}
+
 fn get_hglobal(size: usize, buffer: Vec<u16>) -> Result<HGLOBAL> {
     let handle = unsafe { GlobalAlloc(GMEM_FIXED, size).unwrap() };
     let ptr = unsafe { GlobalLock(handle) };
@@ -360,26 +985,228 @@ pub fn create_instance<T: Interface + ComInterface>(clsid: &GUID) -> Result<T> {
     unsafe { CoCreateInstance(clsid, None, CLSCTX_ALL) }
 }
 
-fn get_file_data_object(paths: &[PathBuf]) -> Option<IDataObject> {
+fn get_file_data_object(paths: &[PathBuf]) -> Result<IDataObject> {
+    unsafe {
+        let shell_item_array = get_shell_item_array(paths)?;
+        shell_item_array.BindToHandler(None, &BHID_DataObject)
+    }
+}
+
+/// The paths to drag, removing the links to renamed files once the drag operation ended.
+fn display_paths(
+    files: Vec<DragFile>,
+    tracker: &DragTracker,
+    options: &Options,
+) -> crate::Result<Vec<PathBuf>> {
+    let (paths, links) = crate::path::display_paths(files)?;
+    if let Some(store) = links {
+        let policy = options.virtual_files_cleanup;
+        tracker
+            .emitter
+            .on_finished(Box::new(move || store.cleanup(policy)));
+    }
+    Ok(paths)
+}
+
+/// Offers the virtual files as file contents, removing the written files once the drag operation ended.
+fn get_virtual_files_data_object(
+    files: Vec<VirtualFile>,
+    tracker: &DragTracker,
+    options: &Options,
+) -> crate::Result<IDataObject> {
+    let store = VirtualFileStore::new(files);
+    let descriptors = file_group_descriptor(&store)?;
+    let policy = options.virtual_files_cleanup;
+    let store_ = store.clone();
+    tracker
+        .emitter
+        .on_finished(Box::new(move || store_.cleanup(policy)));
+    Ok(VirtualFilesDataObject::new(store, descriptors)?.into())
+}
+
+/// Serializes a `FILEGROUPDESCRIPTORW` naming each virtual file.
+fn file_group_descriptor(store: &VirtualFileStore) -> crate::Result<Vec<u8>> {
+    let mut data = (store.len() as u32).to_le_bytes().to_vec();
+    for index in 0..store.len() {
+        let name = store.file_name(index)?;
+        let wide_name = name.encode_utf16().collect::<Vec<_>>();
+        let mut file_name = [0; 260];
+        if wide_name.len() >= file_name.len() {
+            return Err(crate::Error::InvalidPath {
+                path: name.into(),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "the file name is too long",
+                ),
+            });
+        }
+        file_name[..wide_name.len()].copy_from_slice(&wide_name);
+        let descriptor = FILEDESCRIPTORW {
+            dwFlags: (FD_UNICODE.0 | FD_PROGRESSUI.0) as u32,
+            cFileName: file_name,
+            ..Default::default()
+        };
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &descriptor as *const FILEDESCRIPTORW as *const u8,
+                std::mem::size_of::<FILEDESCRIPTORW>(),
+            )
+        };
+        data.extend_from_slice(bytes);
+    }
+    Ok(data)
+}
+
+/// Creates a data object for the files, also offering their shell-quoted paths as text.
+fn get_files_data_object(paths: &[PathBuf], options: &Options) -> Result<IDataObject> {
+    let data_object = get_file_data_object(paths)?;
+    let text = crate::path::shell_quoted_list(paths, options.file_text_separator);
+    set_hglobal_data(&data_object, CF_UNICODETEXT.0, &wide_bytes(&text))?;
+    Ok(data_object)
+}
+
+/// Creates a data object holding the given clipboard formats.
+fn get_data_object(formats: &[(u16, Vec<u8>)]) -> Result<IDataObject> {
+    let data_object: IDataObject = unsafe { SHCreateDataObject(None, None, None)? };
+    for (format, data) in formats {
+        set_hglobal_data(&data_object, *format, data)?;
+    }
+    Ok(data_object)
+}
+
+fn url_clipboard_format() -> u16 {
+    unsafe { RegisterClipboardFormatW(w!("UniformResourceLocatorW")) as u16 }
+}
+
+fn html_clipboard_format() -> u16 {
+    unsafe { RegisterClipboardFormatW(w!("HTML Format")) as u16 }
+}
+
+/// Wraps the HTML fragment with the header of the `HTML Format` clipboard format.
+fn cf_html(html: &str) -> Vec<u8> {
+    const PREFIX: &str = "<html><body>\r\n<!--StartFragment-->";
+    const SUFFIX: &str = "<!--EndFragment-->\r\n</body></html>";
+
+    fn header(
+        start_html: usize,
+        end_html: usize,
+        start_fragment: usize,
+        end_fragment: usize,
+    ) -> String {
+        format!(
+            concat!(
+                "Version:0.9\r\n",
+                "StartHTML:{:010}\r\n",
+                "EndHTML:{:010}\r\n",
+                "StartFragment:{:010}\r\n",
+                "EndFragment:{:010}\r\n",
+            ),
+            start_html, end_html, start_fragment, end_fragment
+        )
+    }
+
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + PREFIX.len();
+    let end_fragment = start_fragment + html.len();
+    let end_html = end_fragment + SUFFIX.len();
+
+    let mut data = header(start_html, end_html, start_fragment, end_fragment).into_bytes();
+    data.extend_from_slice(PREFIX.as_bytes());
+    data.extend_from_slice(html.as_bytes());
+    data.extend_from_slice(SUFFIX.as_bytes());
+    data.push(0);
+    data
+}
+
+/// Encodes the text as a nul terminated UTF-16 string.
+fn wide_bytes(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain(once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Copies the data to a global memory object and stores it in the data object for the given clipboard format.
+fn set_hglobal_data(data_object: &IDataObject, format: u16, data: &[u8]) -> Result<()> {
     unsafe {
-        let shell_item_array = get_shell_item_array(paths).unwrap();
-        shell_item_array.BindToHandler(None, &BHID_DataObject).ok()
+        let handle = global_bytes(data)?;
+        let format_etc = FORMATETC {
+            cfFormat: format,
+            ptd: std::ptr::null_mut(),
+            dwAspect: DVASPECT_CONTENT.0,
+            lindex: -1,
+            tymed: TYMED_HGLOBAL.0 as u32,
+        };
+        let medium = STGMEDIUM {
+            tymed: TYMED_HGLOBAL.0 as u32,
+            u: STGMEDIUM_0 { hGlobal: handle },
+            pUnkForRelease: std::mem::ManuallyDrop::new(None),
+        };
+        data_object.SetData(&format_etc, &medium, TRUE)
     }
 }
 
-fn get_shell_item_array(paths: &[PathBuf]) -> Option<IShellItemArray> {
+/// Copies the data to a global memory object.
+fn global_bytes(data: &[u8]) -> Result<HGLOBAL> {
     unsafe {
-        let list: Vec<*const Common::ITEMIDLIST> = paths
-            .iter()
-            .map(|path| get_file_item_id(path).cast_const())
-            .collect();
-        SHCreateShellItemArrayFromIDLists(&list).ok()
+        let handle = GlobalAlloc(GMEM_MOVEABLE, data.len())?;
+        let ptr = GlobalLock(handle);
+        std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+        let _ = GlobalUnlock(handle);
+        Ok(handle)
     }
 }
 
-fn get_file_item_id(path: &Path) -> *mut Common::ITEMIDLIST {
+fn get_shell_item_array(paths: &[PathBuf]) -> Result<IShellItemArray> {
     unsafe {
-        let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+        let list = paths
+            .iter()
+            .map(|path| get_file_item_id(path).map(|item_id| item_id.cast_const()))
+            .collect::<Result<Vec<*const Common::ITEMIDLIST>>>()?;
+        SHCreateShellItemArrayFromIDLists(&list)
+    }
+}
+
+fn get_file_item_id(path: &Path) -> Result<*mut Common::ITEMIDLIST> {
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+    let item_id = unsafe {
         windows::Win32::UI::Shell::ILCreateFromPathW(PCWSTR::from_raw(wide_path.as_ptr()))
+    };
+    if item_id.is_null() {
+        let message = format!("failed to get the item id of `{}`", path.display());
+        Err(Error::new(E_FAIL, HSTRING::from(message)))
+    } else {
+        Ok(item_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cf_html;
+
+    #[test]
+    fn cf_html_offsets() {
+        for html in ["", "<b>bold</b>", "résumé <i>100%</i>\r\n<p>€</p>"] {
+            let data = cf_html(html);
+            let header = std::str::from_utf8(&data).unwrap();
+            let offset = |name: &str| -> usize {
+                header
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            };
+            let (start_html, end_html) = (offset("StartHTML:"), offset("EndHTML:"));
+            let (start_fragment, end_fragment) = (offset("StartFragment:"), offset("EndFragment:"));
+
+            assert!(header.starts_with("Version:0.9\r\n"));
+            assert_eq!(&data[start_fragment..end_fragment], html.as_bytes());
+            assert!(data[start_html..start_fragment].starts_with(b"<html>"));
+            assert!(data[end_fragment..end_html].ends_with(b"</html>"));
+            // the data ends with a nul terminator after the HTML
+            assert_eq!(end_html, data.len() - 1);
+            assert_eq!(data.last(), Some(&0));
+        }
     }
 }
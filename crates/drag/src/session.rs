@@ -0,0 +1,129 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+};
+
+use crate::{CursorPosition, DragAction, DragEvent, DragEventHandler, DragResult};
+
+#[derive(Default)]
+struct SessionState {
+    active: bool,
+    cancelled: bool,
+    action: Option<DragAction>,
+    position: Option<CursorPosition>,
+}
+
+/// Forwards the drag events to [`crate::Options::on_event`] and keeps the [`DragSession`] state up to date.
+pub(crate) struct DragEmitter {
+    on_event: Option<DragEventHandler>,
+    on_finished: RefCell<Vec<Box<dyn FnOnce()>>>,
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl DragEmitter {
+    pub(crate) fn new(on_event: Option<DragEventHandler>) -> Self {
+        Self {
+            on_event,
+            on_finished: Default::default(),
+            state: Default::default(),
+        }
+    }
+
+    pub(crate) fn emit(&self, event: DragEvent) {
+        {
+            let mut state = self.state.lock().unwrap();
+            match event {
+                DragEvent::Started => state.active = true,
+                DragEvent::Moved(position) => state.position = Some(position),
+                DragEvent::ActionChanged(action) => state.action = action,
+                DragEvent::Finished(result) => {
+                    state.active = false;
+                    state.action = match result {
+                        DragResult::Dropped(action) => Some(action),
+                        DragResult::Cancel(_) => None,
+                    };
+                }
+                DragEvent::LeftSourceWindow | DragEvent::ReenteredSourceWindow => {}
+            }
+        }
+
+        if let Some(on_event) = &self.on_event {
+            on_event(event);
+        }
+
+        if let DragEvent::Finished(_) = event {
+            for hook in self.on_finished.take() {
+                hook();
+            }
+        }
+    }
+
+    /// Registers a hook called once the drag operation ended, after the [`DragEvent::Finished`] event.
+    pub(crate) fn on_finished(&self, hook: Box<dyn FnOnce()>) {
+        self.on_finished.borrow_mut().push(hook);
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn is_active(&self) -> bool {
+        self.state.lock().unwrap().active
+    }
+
+    /// Whether the operation was cancelled with [`DragSession::cancel`].
+    #[cfg(target_os = "linux")]
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    pub(crate) fn session(&self, canceller: Option<Box<dyn FnOnce()>>) -> DragSession {
+        DragSession {
+            state: self.state.clone(),
+            canceller: RefCell::new(canceller),
+        }
+    }
+}
+
+/// Handle to a drag operation started with [`crate::start_drag`].
+///
+/// Dropping the session releases the handle and lets the drag operation run to completion,
+/// use [`DragSession::cancel`] to abort it.
+///
+/// - **Windows**: The drag operation is modal, so it has already ended when the session is returned.
+/// - **macOS**: Cancelling is not supported.
+pub struct DragSession {
+    state: Arc<Mutex<SessionState>>,
+    canceller: RefCell<Option<Box<dyn FnOnce()>>>,
+}
+
+impl DragSession {
+    /// Cancels the drag operation if it is still active.
+    ///
+    /// The drop callback is called with [`crate::CancelReason::UserCancelled`].
+    pub fn cancel(&self) {
+        if !self.is_active() {
+            return;
+        }
+        if let Some(canceller) = self.canceller.borrow_mut().take() {
+            self.state.lock().unwrap().cancelled = true;
+            canceller();
+        }
+    }
+
+    /// Whether the drag operation is still in progress.
+    pub fn is_active(&self) -> bool {
+        self.state.lock().unwrap().active
+    }
+
+    /// The action proposed by the target under the cursor, or the action performed once the item was dropped.
+    pub fn action(&self) -> Option<DragAction> {
+        self.state.lock().unwrap().action
+    }
+
+    /// The last known cursor position, in screen coordinates.
+    pub fn position(&self) -> Option<CursorPosition> {
+        self.state.lock().unwrap().position
+    }
+}
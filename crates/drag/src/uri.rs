@@ -0,0 +1,291 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Conversion between file paths and `file` URIs as specified by [RFC 8089](https://www.rfc-editor.org/rfc/rfc8089).
+//!
+//! Paths are percent-encoded from their raw bytes, so any path round-trips losslessly on Unix,
+//! including paths that are not valid UTF-8.
+//!
+//! ```
+//! # #[cfg(unix)]
+//! # {
+//! use std::path::Path;
+//!
+//! let path = Path::new("/home/user/100% #1 résumé.txt");
+//! let uri = drag::uri::from_path(path, None).unwrap();
+//! assert_eq!(uri, "file:///home/user/100%25%20%231%20r%C3%A9sum%C3%A9.txt");
+//! assert_eq!(drag::uri::to_path(&uri).unwrap(), (path.to_path_buf(), None));
+//!
+//! let uri = drag::uri::from_path(path, Some("workstation")).unwrap();
+//! assert_eq!(uri, "file://workstation/home/user/100%25%20%231%20r%C3%A9sum%C3%A9.txt");
+//! assert_eq!(
+//!   drag::uri::to_path(&uri).unwrap(),
+//!   (path.to_path_buf(), Some("workstation".to_string()))
+//! );
+//! # }
+//! ```
+//!
+//! Every byte value survives the round-trip:
+//!
+//! ```
+//! # #[cfg(unix)]
+//! # {
+//! use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+//!
+//! for byte in 1..=u8::MAX {
+//!   let bytes = [b'/', b'a', byte, b'z', b'/', byte, byte];
+//!   let path = Path::new(OsStr::from_bytes(&bytes));
+//!   let uri = drag::uri::from_path(path, None).unwrap();
+//!   assert!(uri.is_ascii());
+//!   assert_eq!(drag::uri::to_path(&uri).unwrap().0, path);
+//! }
+//! # }
+//! ```
+//!
+//! Relative paths can not be encoded, since a `file` URI always holds an absolute path.
+//!
+//! - **Windows**: Drive letter paths are encoded as `file:///C:/...` and UNC paths as `file://server/share/...`.
+//!   Paths that are not valid Unicode are encoded lossily.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(target_os = "macos"))]
+use crate::DragUri;
+use crate::Error;
+
+/// Characters of a path that are not percent-encoded, besides the ASCII alphanumerics.
+const PATH_SAFE: &[u8] = b"-._~!$&'()*+,;=:@/";
+/// Characters of a hostname that are not percent-encoded, besides the ASCII alphanumerics.
+///
+/// `:` and `@` separate the port and the user information from the hostname.
+const HOST_SAFE: &[u8] = b"-._~!$&'()*+,;=";
+
+/// Encodes the path as a `file` URI, with an optional hostname for paths on a remote machine.
+///
+/// Returns [`Error::InvalidPath`] if the path is not absolute.
+pub fn from_path(path: &Path, host: Option<&str>) -> crate::Result<String> {
+    if !path.is_absolute() {
+        return Err(Error::InvalidPath {
+            path: path.to_path_buf(),
+            source: io::Error::new(io::ErrorKind::InvalidInput, "the path is not absolute"),
+        });
+    }
+
+    #[cfg(unix)]
+    let (host, path) = {
+        use std::os::unix::ffi::OsStrExt;
+        (
+            host.map(str::to_string),
+            path.as_os_str().as_bytes().to_vec(),
+        )
+    };
+
+    #[cfg(windows)]
+    let (host, path) = {
+        let path = path.to_string_lossy().replace('\\', "/");
+        match path.strip_prefix("//") {
+            // UNC path: //server/share/...
+            Some(unc) => {
+                let (server, path) = unc.split_once('/').unwrap_or((unc, ""));
+                (
+                    Some(host.unwrap_or(server).to_string()),
+                    format!("/{path}").into_bytes(),
+                )
+            }
+            None => (host.map(str::to_string), format!("/{path}").into_bytes()),
+        }
+    };
+
+    let mut uri = String::from("file://");
+    if let Some(host) = &host {
+        percent_encode(host.as_bytes(), HOST_SAFE, &mut uri);
+    }
+    percent_encode(&path, PATH_SAFE, &mut uri);
+    Ok(uri)
+}
+
+/// Decodes a `file` URI into the path and the hostname, if the URI has a hostname other than `localhost`.
+///
+/// The `file:/path`, `file:///path` and `file://host/path` forms are accepted.
+pub fn to_path(uri: &str) -> crate::Result<(PathBuf, Option<String>)> {
+    let invalid = || Error::InvalidFileUri(uri.to_string());
+
+    let rest = uri
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("file:"))
+        .map(|_| &uri[5..])
+        .ok_or_else(invalid)?;
+    // the query and fragment are not part of the path
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+
+    let (host, path) = match rest.strip_prefix("//") {
+        Some(authority_and_path) => {
+            let (host, path) = match authority_and_path.find('/') {
+                Some(i) => authority_and_path.split_at(i),
+                None => return Err(invalid()),
+            };
+            let host = String::from_utf8(percent_decode(host).ok_or_else(invalid)?)
+                .map_err(|_| invalid())?;
+            (Some(host), path)
+        }
+        None if rest.starts_with('/') => (None, rest),
+        None => return Err(invalid()),
+    };
+    let host = host.filter(|host| !host.is_empty() && !host.eq_ignore_ascii_case("localhost"));
+    let path = percent_decode(path).ok_or_else(invalid)?;
+
+    #[cfg(unix)]
+    let path = {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+        PathBuf::from(OsString::from_vec(path))
+    };
+
+    #[cfg(windows)]
+    let path = {
+        let path = String::from_utf8(path).map_err(|_| invalid())?;
+        match &host {
+            Some(host) => PathBuf::from(format!("\\\\{host}{}", path.replace('/', "\\"))),
+            None => {
+                // drive letter paths are encoded as /C:/...
+                let path = match path.as_bytes() {
+                    [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+                    _ => &path,
+                };
+                PathBuf::from(path.replace('/', "\\"))
+            }
+        }
+    };
+
+    Ok((path, host))
+}
+
+fn percent_encode(bytes: &[u8], safe: &[u8], out: &mut String) {
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || safe.contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+}
+
+/// Decodes the percent-encoded bytes, returning `None` for malformed escapes.
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Encodes the URIs as a `text/x-moz-url` list, with the URI and the title of each entry on separate lines.
+#[cfg(target_os = "linux")]
+pub(crate) fn moz_url_list(uris: &[DragUri]) -> String {
+    uris.iter()
+        .map(|uri| format!("{}\n{}", uri.uri, uri.title_or_uri()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The plain text fallback of a URI list, one URI per line.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn text_list(uris: &[DragUri]) -> String {
+    uris.iter()
+        .map(|uri| uri.uri.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_path, to_path};
+    use std::path::Path;
+
+    #[test]
+    fn relative_paths_are_rejected() {
+        for path in ["", "foo", "foo/bar", "./foo", "../foo"] {
+            assert!(from_path(Path::new(path), None).is_err(), "{path:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn host_separators_are_encoded() {
+        for (host, uri) in [
+            ("workstation", "file://workstation/tmp"),
+            ("user@host", "file://user%40host/tmp"),
+            ("host:8080", "file://host%3A8080/tmp"),
+            ("a/b?c#d", "file://a%2Fb%3Fc%23d/tmp"),
+        ] {
+            assert_eq!(from_path(Path::new("/tmp"), Some(host)).unwrap(), uri);
+            assert_eq!(
+                to_path(uri).unwrap(),
+                (Path::new("/tmp").to_path_buf(), Some(host.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_paths() {
+        for (path, uri) in [
+            (r"C:\Users\me\a b.txt", "file:///C:/Users/me/a%20b.txt"),
+            (r"\\server\share\file", "file://server/share/file"),
+        ] {
+            assert_eq!(from_path(Path::new(path), None).unwrap(), uri);
+            assert_eq!(to_path(uri).unwrap().0, Path::new(path));
+        }
+    }
+
+    #[cfg(unix)]
+    mod round_trip {
+        use super::{from_path, to_path};
+        use proptest::prelude::*;
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+        proptest! {
+            #[test]
+            fn path_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+                let path = [b"/".as_slice(), &bytes].concat();
+                let path = Path::new(OsStr::from_bytes(&path));
+                let uri = from_path(path, None).unwrap();
+                prop_assert!(uri.is_ascii());
+
+                let (decoded, host) = to_path(&uri).unwrap();
+                prop_assert_eq!(decoded.as_os_str().as_bytes(), path.as_os_str().as_bytes());
+                prop_assert_eq!(host, None);
+            }
+
+            #[test]
+            fn path_and_host(
+                bytes in proptest::collection::vec(any::<u8>(), 0..64),
+                host in "\\PC+".prop_filter("maps to no host", |host| !host.eq_ignore_ascii_case("localhost")),
+            ) {
+                let path = [b"/".as_slice(), &bytes].concat();
+                let path = Path::new(OsStr::from_bytes(&path));
+                let uri = from_path(path, Some(&host)).unwrap();
+                prop_assert!(uri.is_ascii());
+
+                let (decoded, decoded_host) = to_path(&uri).unwrap();
+                prop_assert_eq!(decoded.as_os_str().as_bytes(), path.as_os_str().as_bytes());
+                prop_assert_eq!(decoded_host, Some(host));
+            }
+        }
+    }
+}
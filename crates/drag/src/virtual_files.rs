@@ -0,0 +1,226 @@
+// Copyright 2023-2023 CrabNebula Ltd.
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+use crate::{CleanupPolicy, VirtualFile};
+
+struct Entry {
+    name: String,
+    contents: Option<crate::ContentProvider>,
+    path: Option<PathBuf>,
+}
+
+impl Entry {
+    fn file_name(&self) -> io::Result<&OsStr> {
+        // only keep the file name so the file can't be written outside of the directory
+        Path::new(&self.name).file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid virtual file name `{}`", self.name),
+            )
+        })
+    }
+
+    fn take_contents(&mut self) -> io::Result<Vec<u8>> {
+        let contents = self.contents.take().ok_or_else(|| {
+            io::Error::other(format!("failed to provide the contents of `{}`", self.name))
+        })?;
+        contents()
+    }
+}
+
+/// Writes the virtual files of a drag operation to a temporary directory when a drop target first asks for them,
+/// and links renamed files from there.
+pub(crate) struct VirtualFileStore {
+    dir: Mutex<Option<tempfile::TempDir>>,
+    entries: Vec<Mutex<Entry>>,
+    cleaned_up: AtomicBool,
+    /// Whether the directory outlives the store, with [`CleanupPolicy::Never`].
+    keep: AtomicBool,
+}
+
+impl VirtualFileStore {
+    pub(crate) fn new(files: Vec<VirtualFile>) -> Arc<Self> {
+        Arc::new(Self {
+            dir: Mutex::new(None),
+            entries: files
+                .into_iter()
+                .map(|file| {
+                    Mutex::new(Entry {
+                        name: file.name,
+                        contents: Some(file.contents),
+                        path: None,
+                    })
+                })
+                .collect(),
+            cleaned_up: AtomicBool::new(false),
+            keep: AtomicBool::new(false),
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Writes the file if needed and returns its path.
+    pub(crate) fn materialize(&self, index: usize) -> io::Result<PathBuf> {
+        let mut entry = self.entries[index].lock().unwrap();
+        if let Some(path) = &entry.path {
+            return Ok(path.clone());
+        }
+
+        // each file is written into its own subdirectory so files with the same name don't collide
+        let dir = self.dir()?.join(index.to_string());
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(entry.file_name()?);
+        std::fs::write(&path, entry.take_contents()?)?;
+        entry.path.replace(path.clone());
+        Ok(path)
+    }
+
+    /// Writes the file to the location chosen by the drop target instead of the temporary directory.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn save_to(&self, index: usize, path: &Path) -> io::Result<()> {
+        let mut entry = self.entries[index].lock().unwrap();
+        match &entry.path {
+            Some(written) => {
+                std::fs::copy(written, path)?;
+            }
+            None => std::fs::write(path, entry.take_contents()?)?,
+        }
+        entry.path.replace(path.to_path_buf());
+        Ok(())
+    }
+
+    /// The name of the file, without any directory.
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) fn file_name(&self, index: usize) -> io::Result<String> {
+        let entry = self.entries[index].lock().unwrap();
+        entry
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    /// Links the file from the temporary directory under another name.
+    ///
+    /// Each file is linked into its own subdirectory so files with the same name don't collide.
+    pub(crate) fn link(&self, index: usize, target: &Path, name: &str) -> io::Result<PathBuf> {
+        let file_name = Path::new(name).file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid file name `{name}`"),
+            )
+        })?;
+        let dir = self.dir()?.join(index.to_string());
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(file_name);
+
+        // hard links only work on the same file system
+        if std::fs::hard_link(target, &path).is_err() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(target, &path)?;
+            // creating symbolic links requires special privileges on Windows
+            #[cfg(windows)]
+            std::fs::copy(target, &path).map(|_| ())?;
+        }
+        Ok(path)
+    }
+
+    /// Writes all the files, skipping the ones that fail.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn materialize_all(&self) -> Vec<PathBuf> {
+        (0..self.len())
+            .filter_map(|index| self.materialize(index).ok())
+            .collect()
+    }
+
+    /// Removes the directory according to the policy, once the drag operation ended.
+    pub(crate) fn cleanup(self: &Arc<Self>, policy: CleanupPolicy) {
+        match policy {
+            CleanupPolicy::Immediately => self.remove(),
+            // give the drop target time to copy the files
+            CleanupPolicy::After(delay) => schedule_removal(Instant::now() + delay, self.clone()),
+            CleanupPolicy::Never => {
+                let mut dir = self.dir.lock().unwrap();
+                self.keep.store(true, Ordering::Relaxed);
+                if let Some(dir) = dir.as_mut() {
+                    dir.disable_cleanup(true);
+                }
+            }
+        }
+    }
+
+    /// Removes the directory, files can't be written afterwards.
+    fn remove(&self) {
+        let mut dir = self.dir.lock().unwrap();
+        self.cleaned_up.store(true, Ordering::Relaxed);
+        dir.take();
+    }
+
+    fn dir(&self) -> io::Result<PathBuf> {
+        let mut dir = self.dir.lock().unwrap();
+        if self.cleaned_up.load(Ordering::Relaxed) {
+            return Err(io::Error::other("the drag operation already ended"));
+        }
+        if dir.is_none() {
+            let mut created = tempfile::Builder::new().prefix("drag-rs").tempdir()?;
+            created.disable_cleanup(self.keep.load(Ordering::Relaxed));
+            dir.replace(created);
+        }
+        Ok(dir.as_ref().unwrap().path().to_path_buf())
+    }
+}
+
+type PendingRemoval = (Instant, Arc<VirtualFileStore>);
+
+/// Removes the directory of the store at the given time, on a single thread shared by all drag operations.
+fn schedule_removal(at: Instant, store: Arc<VirtualFileStore>) {
+    static SENDER: OnceLock<Mutex<mpsc::Sender<PendingRemoval>>> = OnceLock::new();
+    let sender = SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("drag-rs-cleanup".into())
+            .spawn(move || remove_when_due(receiver))
+            .expect("failed to spawn the virtual files cleanup thread");
+        Mutex::new(sender)
+    });
+    // the receiver lives as long as the process
+    let _ = sender.lock().unwrap().send((at, store));
+}
+
+fn remove_when_due(receiver: mpsc::Receiver<PendingRemoval>) {
+    let mut pending: Vec<PendingRemoval> = Vec::new();
+    loop {
+        let received = match pending.iter().map(|(at, _)| *at).min() {
+            Some(next) => receiver.recv_timeout(next.saturating_duration_since(Instant::now())),
+            None => receiver
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(removal) => pending.push(removal),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        pending.retain(|(at, store)| {
+            let due = *at <= now;
+            if due {
+                store.remove();
+            }
+            !due
+        });
+    }
+}
@@ -2,12 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use std::{
-    fs::read,
-    io::Write,
-    path::PathBuf,
-    sync::{mpsc::channel, Arc, Mutex},
-};
+use std::fs::read;
 
 use base64::Engine;
 use serde::{ser::Serializer, Serialize};
@@ -15,7 +10,7 @@ use tauri::{
     api::ipc::CallbackFn,
     command,
     plugin::{Builder, TauriPlugin},
-    AppHandle, FileDropEvent, Manager, Runtime, Window, WindowEvent,
+    FileDropEvent, Manager, Runtime, Window, WindowEvent,
 };
 
 type Result<T> = std::result::Result<T, Error>;
@@ -85,24 +80,28 @@ async fn on_drop<R: Runtime>(window: Window<R>, handler: CallbackFn) -> Result<(
 
 #[command]
 async fn drag_new_window<R: Runtime>(
-    app: AppHandle<R>,
     window: Window<R>,
     image_base64: String,
     on_event_fn: Option<CallbackFn>,
 ) -> Result<()> {
     perform_drag(
-        app,
         window,
         DragData::Data,
         image_base64,
         on_event_fn,
-        || {},
+        // only create a window if the element was not dropped on a target nor cancelled by the user
+        |result| {
+            matches!(
+                result,
+                drag::DragResult::Cancel(drag::CancelReason::NoTarget)
+            )
+        },
     )
+    .await
 }
 
 #[command]
 async fn drag_back<R: Runtime>(
-    app: AppHandle<R>,
     window: Window<R>,
     data: serde_json::Value,
     image_base64: String,
@@ -110,45 +109,28 @@ async fn drag_back<R: Runtime>(
 ) -> Result<()> {
     let data = serde_json::to_vec(&data)?;
 
-    let mut file = tempfile::Builder::new().prefix(FILE_PREFIX).tempfile()?;
-    file.write_all(&data)?;
-    file.flush()?;
-    let path = file.path().to_path_buf();
-
-    let file = Arc::new(Mutex::new(Some(file)));
-
     perform_drag(
-        app,
         window,
-        DragData::Path(path),
+        DragData::File(data),
         image_base64,
         on_event_fn,
-        move || {
-            let file_ = file.clone();
-            // wait a litle to delete the file
-            std::thread::spawn(move || {
-                std::thread::sleep(std::time::Duration::from_secs(3));
-                file_.lock().unwrap().take();
-            });
-        },
+        |_| true,
     )
+    .await
 }
 
 enum DragData {
-    Path(PathBuf),
+    File(Vec<u8>),
     Data,
 }
 
-fn perform_drag<R: Runtime, F: Fn() + Send + Sync + 'static>(
-    app: AppHandle<R>,
+async fn perform_drag<R: Runtime>(
     window: Window<R>,
     data: DragData,
     image_base64: String,
     on_event_fn: Option<CallbackFn>,
-    handler: F,
+    should_notify: fn(&drag::DragResult) -> bool,
 ) -> Result<()> {
-    let (tx, rx) = channel();
-
     let image = drag::Image::Raw(
         base64::engine::general_purpose::STANDARD.decode(
             image_base64
@@ -157,45 +139,48 @@ fn perform_drag<R: Runtime, F: Fn() + Send + Sync + 'static>(
         )?,
     );
 
-    app.run_on_main_thread(move || {
-        #[cfg(target_os = "linux")]
-        let raw_window = window.gtk_window();
-        #[cfg(not(target_os = "linux"))]
-        let raw_window = tauri::Result::Ok(window.clone());
-
-        let r = match raw_window {
-            Ok(w) => drag::start_drag(
-                &w,
-                match data {
-                    DragData::Path(p) => drag::DragItem::Files(vec![p]),
-                    DragData::Data => drag::DragItem::Data {
-                        provider: Box::new(|_type| Some(Vec::new())),
-                        types: vec![window.config().tauri.bundle.identifier.clone()],
-                    },
-                },
-                image,
-                move |result, cursor_pos| {
-                    if let Some(on_event_fn) = on_event_fn {
-                        let callback_result = CallbackResult { result, cursor_pos };
-                        let js = tauri::api::ipc::format_callback(on_event_fn, &callback_result)
-                            .expect("unable to serialize CallbackResult");
-
-                        let _ = window.eval(js.as_str());
-                    }
-
-                    handler();
-                },
-                drag::Options {
-                    skip_animatation_on_cancel_or_failure: true,
-                },
-            )
-            .map_err(Into::into),
-            Err(e) => Err(e.into()),
+    let identifier = window.config().tauri.bundle.identifier.clone();
+    let item = move || match data {
+        DragData::File(data) => drag::DragItem::VirtualFiles(vec![drag::VirtualFile::new(
+            format!("{FILE_PREFIX}.json"),
+            move || Ok(data),
+        )]),
+        DragData::Data => drag::DragItem::Data {
+            provider: Box::new(|_type| Some(Vec::new())),
+            types: vec![identifier],
+        },
+    };
+
+    let window_ = window.clone();
+    let outcome = drag::start_drag_async(
+        move || {
+            #[cfg(target_os = "linux")]
+            let raw_window = window_.gtk_window();
+            #[cfg(not(target_os = "linux"))]
+            let raw_window = tauri::Result::Ok(window_);
+            raw_window
+        },
+        item,
+        image,
+        drag::Options {
+            skip_animatation_on_cancel_or_failure: true,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    if let Some(on_event_fn) = on_event_fn.filter(|_| should_notify(&outcome.result)) {
+        let callback_result = CallbackResult {
+            result: outcome.result,
+            cursor_pos: outcome.cursor_position,
         };
-        tx.send(r).unwrap();
-    })?;
+        let js = tauri::api::ipc::format_callback(on_event_fn, &callback_result)
+            .expect("unable to serialize CallbackResult");
 
-    rx.recv().unwrap()
+        let _ = window.eval(js.as_str());
+    }
+
+    Ok(())
 }
 
 /// Initializes the plugin.
@@ -2,14 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
-use std::{collections::HashMap, path::PathBuf, sync::mpsc::channel};
+use std::{collections::HashMap, path::PathBuf};
 
 use serde::{ser::Serializer, Deserialize, Deserializer, Serialize};
 use tauri::{
     api::ipc::CallbackFn,
     command,
     plugin::{Builder, TauriPlugin},
-    AppHandle, Runtime, Window,
+    Runtime, Window,
 };
 
 type Result<T> = std::result::Result<T, Error>;
@@ -63,7 +63,13 @@ enum DragItem {
     /// A list of files to be dragged.
     ///
     /// The paths must be absolute.
-    Files(Vec<PathBuf>),
+    Files(Vec<File>),
+    /// A list of URIs such as web links.
+    Uris { uris: Vec<drag::DragUri> },
+    /// Rich text with an optional plain text fallback.
+    Html { html: String, text: Option<String> },
+    /// Plain text.
+    Text { text: String },
     /// Data to share with another app.
     Data {
         data: SharedData,
@@ -71,6 +77,23 @@ enum DragItem {
     },
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum File {
+    Path(PathBuf),
+    /// A file with a display name.
+    Named(drag::DragFile),
+}
+
+impl From<File> for drag::DragFile {
+    fn from(file: File) -> Self {
+        match file {
+            File::Path(path) => path.into(),
+            File::Named(file) => file,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum SharedData {
@@ -87,14 +110,11 @@ struct CallbackResult {
 
 #[command]
 async fn start_drag<R: Runtime>(
-    app: AppHandle<R>,
     window: Window<R>,
     item: DragItem,
     image: Image,
     on_event_fn: Option<CallbackFn>,
 ) -> Result<()> {
-    let (tx, rx) = channel();
-
     let image = match image {
         Image::Raw(r) => r,
         Image::Base64(b) => {
@@ -103,47 +123,48 @@ async fn start_drag<R: Runtime>(
         }
     };
 
-    app.run_on_main_thread(move || {
-        #[cfg(target_os = "linux")]
-        let raw_window = window.gtk_window();
-        #[cfg(not(target_os = "linux"))]
-        let raw_window = tauri::Result::Ok(window.clone());
-
-        let r = match raw_window {
-            Ok(w) => drag::start_drag(
-                &w,
-                match item {
-                    DragItem::Files(f) => drag::DragItem::Files(f),
-                    DragItem::Data { data, types } => drag::DragItem::Data {
-                        provider: Box::new(move |data_type| match &data {
-                            SharedData::Fixed(d) => Some(d.as_bytes().to_vec()),
-                            SharedData::Map(m) => m.get(data_type).map(|d| d.as_bytes().to_vec()),
-                        }),
-                        types,
-                    },
-                },
-                image,
-                move |result, cursor_pos| {
-                    if let Some(on_event_fn) = on_event_fn {
-                        let callback_result = CallbackResult { result, cursor_pos };
-                        let js = tauri::api::ipc::format_callback(
-                            on_event_fn,
-                            &serde_json::to_string(&callback_result).unwrap(),
-                        )
-                        .expect("unable to serialize DragResult");
-
-                        let _ = window.eval(js.as_str());
-                    }
-                },
-                Default::default(),
-            )
-            .map_err(Into::into),
-            Err(e) => Err(e.into()),
+    let window_ = window.clone();
+    let outcome = drag::start_drag_async(
+        move || {
+            #[cfg(target_os = "linux")]
+            let raw_window = window_.gtk_window();
+            #[cfg(not(target_os = "linux"))]
+            let raw_window = tauri::Result::Ok(window_);
+            raw_window
+        },
+        move || match item {
+            DragItem::Files(f) => drag::DragItem::Files(f.into_iter().map(Into::into).collect()),
+            DragItem::Text { text } => drag::DragItem::Text(text),
+            DragItem::Uris { uris } => drag::DragItem::Uris(uris),
+            DragItem::Html { html, text } => drag::DragItem::Html { html, text },
+            DragItem::Data { data, types } => drag::DragItem::Data {
+                provider: Box::new(move |data_type| match &data {
+                    SharedData::Fixed(d) => Some(d.as_bytes().to_vec()),
+                    SharedData::Map(m) => m.get(data_type).map(|d| d.as_bytes().to_vec()),
+                }),
+                types,
+            },
+        },
+        image,
+        Default::default(),
+    )
+    .await?;
+
+    if let Some(on_event_fn) = on_event_fn {
+        let callback_result = CallbackResult {
+            result: outcome.result,
+            cursor_pos: outcome.cursor_position,
         };
-        tx.send(r).unwrap();
-    })?;
+        let js = tauri::api::ipc::format_callback(
+            on_event_fn,
+            &serde_json::to_string(&callback_result).unwrap(),
+        )
+        .expect("unable to serialize DragResult");
+
+        let _ = window.eval(js.as_str());
+    }
 
-    rx.recv().unwrap()
+    Ok(())
 }
 
 /// Initializes the plugin.
@@ -49,7 +49,9 @@ fn main() {
                     },
                     #[cfg(not(target_os = "linux"))]
                     &window,
-                    DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png").unwrap()]),
+                    DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png")
+                        .unwrap()
+                        .into()]),
                     Image::Raw(include_bytes!("../../icon.png").to_vec()),
                     // Image::File("./examples/icon.png".into()),
                     |result: DragResult, cursor_pos: CursorPosition| {
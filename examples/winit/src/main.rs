@@ -45,9 +45,9 @@ fn main() {
                 } => {
                     start_drag(
                         &window,
-                        DragItem::Files(
-                            vec![std::fs::canonicalize("./examples/icon.png").unwrap()],
-                        ),
+                        DragItem::Files(vec![std::fs::canonicalize("./examples/icon.png")
+                            .unwrap()
+                            .into()]),
                         Image::Raw(include_bytes!("../../icon.png").to_vec()),
                         // Image::File("./examples/icon.png".into()),
                         |result: DragResult, cursor_pos: CursorPosition| {
@@ -175,7 +175,7 @@ fn main() -> wry::Result<()> {
                     },
                     #[cfg(not(target_os = "linux"))]
                     &webview.window(),
-                    DragItem::Files(paths),
+                    DragItem::Files(paths.into_iter().map(Into::into).collect()),
                     icon,
                     move |result: DragResult, cursor_pos: CursorPosition| {
                         println!(
@@ -92,8 +92,8 @@ fn main() -> wry::Result<()> {
                         #[cfg(not(target_os = "linux"))]
                         &webview.window(),
                         DragItem::Files(vec![
-                            std::fs::canonicalize("./examples/icon.png").unwrap(),
-                            std::fs::canonicalize("./examples/icon.bmp").unwrap(),
+                            std::fs::canonicalize("./examples/icon.png").unwrap().into(),
+                            std::fs::canonicalize("./examples/icon.bmp").unwrap().into(),
                         ]),
                         Image::Raw(include_bytes!("../../icon.png").to_vec()),
                         // Image::File("./examples/icon.png".into()),
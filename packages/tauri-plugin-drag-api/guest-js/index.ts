@@ -1,10 +1,33 @@
 import { invoke, transformCallback } from "@tauri-apps/api/tauri";
 
 export type DragItem =
-  | string[]
+  | (string | DragFile)[]
+  | { uris: DragUri[] }
+  | { text: string }
+  | { html: string; text?: string }
   | { data: string | Record<string, string>; types: string[] };
 
-export type DragResult = "Dropped" | "Cancelled";
+export interface DragFile {
+  path: string;
+  /** The file name seen by the user and the drop target. */
+  name?: string;
+}
+
+export interface DragUri {
+  uri: string;
+  title?: string;
+}
+
+export type DragAction = "Copy" | "Move" | "Link";
+
+export type CancelReason =
+  | "NoTarget"
+  | "UserCancelled"
+  | "TimeoutExpired"
+  | "GrabBroken"
+  | "Error";
+
+export type DragResult = { Dropped: DragAction } | { Cancel: CancelReason };
 
 /**
  * Logical position of the cursor.
@@ -25,7 +48,7 @@ export interface CallbackPayload {
 }
 
 /**
- * Starts a drag operation. Can either send a list of files or data to another app.
+ * Starts a drag operation. Can either send a list of files, text or data to another app.
  *
  * ```typescript
  * import { startDrag } from "@crabnebula/tauri-plugin-drag";
@@ -1,7 +1,16 @@
 import { invoke, transformCallback } from "@tauri-apps/api/tauri";
 import html2canvas from "html2canvas";
 
-type DragResult = "Dropped" | "Cancelled";
+type DragAction = "Copy" | "Move" | "Link";
+
+type CancelReason =
+  | "NoTarget"
+  | "UserCancelled"
+  | "TimeoutExpired"
+  | "GrabBroken"
+  | "Error";
+
+type DragResult = { Dropped: DragAction } | { Cancel: CancelReason };
 
 /**
  * Logical position of the cursor.
@@ -54,7 +63,7 @@ export async function onElementDrop(handler: (data: any) => void) {
  * ```
  *
  * @param el the element or selector to drag
- * @param onDrop on drop handler, used to create the window
+ * @param onDrop on drop handler, used to create the window. Only called when the element is dropped outside of any drop target
  */
 export async function dragAsWindow(
   el: string | HTMLElement,